@@ -0,0 +1,265 @@
+use std::f32::consts::PI;
+
+use bevy::prelude::*;
+
+use crate::camera_rig::CameraRig;
+use crate::input::{InputSettings, PlayerInput};
+use crate::player::{MovementState, MovementStateChanged, Player, PlayerCamera, PlayerMovement, player_kinematics};
+
+pub struct CameraEffectsPlugin;
+
+/// Which movement-driven camera effects are enabled, each can be turned off for motion sensitivity
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CameraEffectSettings {
+	/// Widen the field of view at high speeds
+	pub fov_widening: bool,
+	/// Roll the camera when turning at speed
+	pub turn_roll: bool,
+	/// Dip the camera down when landing
+	pub landing_dip: bool,
+	/// Bob the camera up and down while running on the ground
+	pub head_bob: bool,
+}
+
+impl Default for CameraEffectSettings {
+	fn default() -> Self {
+		Self {
+			fov_widening: true,
+			turn_roll: true,
+			landing_dip: true,
+			head_bob: false,
+		}
+	}
+}
+
+/// Field of view at rest [radian]
+const BASE_FOV: f32 = PI / 4.0;
+/// Field of view added at `FOV_FULL_SPEED` and above [radian]
+const MAX_EXTRA_FOV: f32 = PI / 9.0;
+/// Speed at which the field of view is widest [m/s]
+const FOV_FULL_SPEED: f32 = 40.0;
+/// Strongest roll the camera can have [radian]
+const MAX_ROLL: f32 = PI / 36.0;
+/// Roll per unit of speed times turning rate [radian / (m/s * radian/s)]
+const ROLL_PER_TURN_SPEED: f32 = 0.002;
+/// Camera drop per unit of landing speed [m / (m/s)]
+const DIP_PER_LANDING_SPEED: f32 = 0.02;
+/// Deepest a landing can dip the camera [m]
+const MAX_DIP: f32 = 0.4;
+/// Head bob phase advanced per meter run [radian/m]
+const BOB_FREQUENCY: f32 = 1.5;
+/// Head bob height at `FOV_FULL_SPEED` and above [m]
+const MAX_BOB_AMPLITUDE: f32 = 0.08;
+/// How quickly effects settle towards their targets [1/s]
+const EFFECT_RESPONSE: f32 = 8.0;
+
+/// Movement-driven camera effect state, advanced once per fixed tick along with the player simulation
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct CameraEffects {
+	/// Current vertical field of view [radian]
+	pub fov: f32,
+	/// Current roll around the view axis [radian]
+	pub roll: f32,
+	/// Current downwards offset from landing [m]
+	pub dip: f32,
+	/// Current phase of the head bob [radian]
+	pub bob_phase: f32,
+	/// Current vertical offset from head bob [m]
+	pub bob_offset: f32,
+	/// Vertical speed of the player at the previous tick, gives the landing speed [m/s]
+	previous_vertical_speed: f32,
+}
+
+impl Default for CameraEffects {
+	fn default() -> Self {
+		Self {
+			fov: BASE_FOV,
+			roll: 0.0,
+			dip: 0.0,
+			bob_phase: 0.0,
+			bob_offset: 0.0,
+			previous_vertical_speed: 0.0,
+		}
+	}
+}
+
+impl CameraEffects {
+	/// Advances all effects by one step
+	/// `yaw_rate` is how fast the player is turning [radian/s], `landed` whether the player has just touched down
+	pub fn update(&mut self, settings: &CameraEffectSettings, movement: &PlayerMovement, yaw_rate: f32, landed: bool, dt: f32) {
+		let speed = movement.desired_velocity.length();
+		let horizontal_speed = movement.horizontal(movement.desired_velocity).length();
+		let speed_fraction = f32::min(speed / FOV_FULL_SPEED, 1.0);
+		let response = f32::min(EFFECT_RESPONSE * dt, 1.0);
+
+		let target_fov = if settings.fov_widening {BASE_FOV + speed_fraction * MAX_EXTRA_FOV} else {BASE_FOV};
+		self.fov += (target_fov - self.fov) * response;
+
+		let target_roll = if settings.turn_roll {
+			f32::clamp(-ROLL_PER_TURN_SPEED * yaw_rate * speed, -MAX_ROLL, MAX_ROLL)
+		} else {
+			0.0
+		};
+		self.roll += (target_roll - self.roll) * response;
+
+		if landed && settings.landing_dip {
+			let landing_speed = f32::max(-self.previous_vertical_speed, 0.0);
+			self.dip = f32::max(self.dip, f32::min(landing_speed * DIP_PER_LANDING_SPEED, MAX_DIP));
+		}
+		self.dip -= self.dip * response;
+		self.previous_vertical_speed = movement.vertical(movement.desired_velocity);
+
+		let running = matches!(movement.state, MovementState::Grounded);
+		if settings.head_bob && running {
+			self.bob_phase = (self.bob_phase + horizontal_speed * BOB_FREQUENCY * dt) % (2.0 * PI);
+			let amplitude = MAX_BOB_AMPLITUDE * f32::min(horizontal_speed / FOV_FULL_SPEED, 1.0);
+			self.bob_offset = amplitude * f32::sin(self.bob_phase);
+		} else {
+			self.bob_offset -= self.bob_offset * response;
+		}
+	}
+
+	/// How far the camera is moved up from the eyes, negative for down [m]
+	pub fn vertical_offset(&self) -> f32 {
+		self.bob_offset - self.dip
+	}
+}
+
+impl Plugin for CameraEffectsPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<CameraEffectSettings>()
+			.add_systems(FixedUpdate, (
+				add_camera_effects_to_cameras,
+				update_camera_effects,
+			).chain().after(player_kinematics))
+		;
+	}
+}
+
+fn add_camera_effects_to_cameras(
+	mut commands: Commands,
+	cameras: Query<Entity, (With<PlayerCamera>, Without<CameraEffects>)>,
+) {
+	for camera in cameras.iter() {
+		commands.entity(camera).insert(CameraEffects::default());
+	}
+}
+
+fn update_camera_effects(
+	mut cameras: Query<(&mut Transform, &PlayerCamera, &mut CameraEffects, &Parent), Without<Player>>,
+	mut rigs: Query<(&CameraRig, &mut Projection)>,
+	players: Query<(&PlayerMovement, &PlayerInput), With<Player>>,
+	mut state_events: EventReader<MovementStateChanged>,
+	input_config: Res<InputSettings>,
+	settings: Res<CameraEffectSettings>,
+	time: Res<Time>,
+) {
+	let dt = time.delta_seconds();
+	if dt <= 0.0 {
+		state_events.clear();
+		return;
+	}
+	let landed_players: Vec<Entity> = state_events.read()
+		.filter(|event| matches!(event.from, MovementState::Airborne | MovementState::WallRunning))
+		.filter(|event| matches!(event.to, MovementState::Grounded | MovementState::Sliding))
+		.map(|event| event.player)
+		.collect();
+
+	for (mut transform, camera, mut effects, parent) in cameras.iter_mut() {
+		let Ok((movement, player_input)) = players.get(parent.get()) else {continue;};
+		let yaw_rate = player_input.mouse_delta.x * input_config.camera_sensitivity / dt;
+		let landed = landed_players.contains(&parent.get());
+		effects.update(&settings, movement, yaw_rate, landed, dt);
+
+		// Eye height and pitch get rewritten every tick, so the effects never pile up
+		transform.rotation = Quat::from_rotation_x(camera.pitch()) * Quat::from_rotation_z(effects.roll);
+		transform.translation.y += effects.vertical_offset();
+		for (rig, mut projection) in rigs.iter_mut() {
+			if rig.player != parent.get() {continue;}
+			if let Projection::Perspective(perspective) = projection.as_mut() {
+				perspective.fov = effects.fov;
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const DT: f32 = 1.0 / 64.0;
+
+	fn moving(state: MovementState, velocity: Vec3) -> PlayerMovement {
+		PlayerMovement {
+			state,
+			desired_velocity: velocity,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn fov_widens_with_speed() {
+		let settings = CameraEffectSettings::default();
+		let mut effects = CameraEffects::default();
+		let movement = moving(MovementState::Grounded, Vec3::new(FOV_FULL_SPEED, 0.0, 0.0));
+		effects.update(&settings, &movement, 0.0, false, DT);
+		assert!(effects.fov > BASE_FOV && effects.fov < BASE_FOV + MAX_EXTRA_FOV);
+		for _ in 0..200 {
+			effects.update(&settings, &movement, 0.0, false, DT);
+		}
+		assert!((effects.fov - (BASE_FOV + MAX_EXTRA_FOV)).abs() < 1e-3);
+
+		let mut resting = CameraEffects::default();
+		resting.update(&settings, &moving(MovementState::Grounded, Vec3::ZERO), 0.0, false, DT);
+		assert_eq!(resting.fov, BASE_FOV);
+	}
+
+	#[test]
+	fn fov_stays_put_when_disabled() {
+		let settings = CameraEffectSettings {fov_widening: false, ..Default::default()};
+		let mut effects = CameraEffects::default();
+		effects.update(&settings, &moving(MovementState::Grounded, Vec3::new(FOV_FULL_SPEED, 0.0, 0.0)), 0.0, false, DT);
+		assert_eq!(effects.fov, BASE_FOV);
+	}
+
+	#[test]
+	fn landing_dips_by_previous_fall_speed() {
+		let settings = CameraEffectSettings::default();
+		let mut effects = CameraEffects::default();
+		let landing_speed = 10.0;
+		effects.update(&settings, &moving(MovementState::Airborne, Vec3::new(0.0, -landing_speed, 0.0)), 0.0, false, DT);
+		assert_eq!(effects.dip, 0.0);
+
+		effects.update(&settings, &moving(MovementState::Grounded, Vec3::ZERO), 0.0, true, DT);
+		let expected = DIP_PER_LANDING_SPEED * landing_speed * (1.0 - EFFECT_RESPONSE * DT);
+		assert!((effects.dip - expected).abs() < 1e-6);
+		assert!(effects.vertical_offset() < 0.0);
+	}
+
+	#[test]
+	fn landing_dip_is_capped() {
+		let settings = CameraEffectSettings::default();
+		let mut effects = CameraEffects::default();
+		effects.update(&settings, &moving(MovementState::Airborne, Vec3::new(0.0, -1000.0, 0.0)), 0.0, false, DT);
+		effects.update(&settings, &moving(MovementState::Grounded, Vec3::ZERO), 0.0, true, DT);
+		assert!(effects.dip <= MAX_DIP);
+	}
+
+	#[test]
+	fn head_bob_only_while_grounded() {
+		let settings = CameraEffectSettings {head_bob: true, ..Default::default()};
+		let velocity = Vec3::new(10.0, 0.0, 0.0);
+		for state in [MovementState::Airborne, MovementState::Sliding, MovementState::WallRunning, MovementState::Locked] {
+			let mut effects = CameraEffects::default();
+			for _ in 0..10 {
+				effects.update(&settings, &moving(state, velocity), 0.0, false, DT);
+			}
+			assert_eq!(effects.bob_offset, 0.0, "{state:?} bobs");
+		}
+
+		let mut effects = CameraEffects::default();
+		effects.update(&settings, &moving(MovementState::Grounded, velocity), 0.0, false, DT);
+		assert!(effects.bob_offset > 0.0);
+	}
+}
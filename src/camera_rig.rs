@@ -0,0 +1,320 @@
+use std::f32::consts::PI;
+
+use bevy::{core_pipeline::clear_color::ClearColorConfig, prelude::*, render::camera::Viewport, transform::TransformSystem, window::PrimaryWindow};
+use bevy_rapier3d::prelude::*;
+
+use crate::input::{LocalInput, PlayerInput, SamplePlayerInput};
+use crate::player::{Player, PlayerCamera, PlayerMovement, interpolate_rendered_transform, process_mouse_movement, rotate_player};
+
+/// How far behind the player's eyes the chase camera sits [m]
+const CHASE_DISTANCE: f32 = 4.0;
+/// How close the camera is allowed to get to level geometry [m]
+const CAMERA_COLLISION_MARGIN: f32 = 0.05;
+/// How quickly the free spectator camera flies [m/s]
+const SPECTATOR_SPEED: f32 = 15.0;
+
+pub struct CameraRigPlugin;
+
+/// How cameras are kept out of level geometry and led by the player's velocity
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct CameraRigSettings {
+	/// Radius of the sphere swept towards where the camera wants to be, keeps the near plane out of walls [m]
+	pub collision_radius: f32,
+	/// Move the camera ahead in the direction the player is moving
+	pub look_ahead: bool,
+	/// How far the camera moves ahead per unit of horizontal speed [s]
+	pub look_ahead_per_speed: f32,
+	/// Furthest the camera moves ahead [m]
+	pub max_look_ahead: f32,
+}
+
+impl Default for CameraRigSettings {
+	fn default() -> Self {
+		Self {
+			collision_radius: 0.2,
+			look_ahead: false,
+			look_ahead_per_speed: 0.02,
+			max_look_ahead: 0.5,
+		}
+	}
+}
+
+impl CameraRigSettings {
+	/// Offset the camera moves ahead by at the given velocity, only the part perpendicular to `up` counts
+	pub fn look_ahead_offset(&self, velocity: Vec3, up: Vec3) -> Vec3 {
+		if !self.look_ahead {return Vec3::ZERO;}
+		let horizontal_velocity = velocity - velocity.dot(up) * up;
+		(self.look_ahead_per_speed * horizontal_velocity).clamp_length_max(self.max_look_ahead)
+	}
+}
+
+/// Moves a camera from `pivot` towards `desired`, stopping short of the first thing a sphere of `radius` would hit on the way
+///
+/// `pivot` has to be somewhere the camera is allowed to be, like the inside of the player
+pub fn solve_camera_collision(rapier_context: &RapierContext, pivot: Vec3, desired: Vec3, radius: f32, filter: QueryFilter) -> Vec3 {
+	let offset = desired - pivot;
+	let distance = offset.length();
+	if distance < 0.001 {return desired;}
+	let Some((_, hit)) = rapier_context.cast_shape(pivot, Quat::IDENTITY, offset, &Collider::ball(radius), 1.0, true, filter) else {
+		return desired;
+	};
+	// Stay a little bit away from the surface, so it doesn't flicker into view
+	let free_distance = f32::max(hit.toi * distance - CAMERA_COLLISION_MARGIN, 0.0);
+	pivot + free_distance * offset / distance
+}
+
+/// Part of the window the view of the local player with the given index gets when `count` of them share it,
+/// as fractions of the window size, views are laid out in a grid filled row by row
+pub fn split_screen_rect(index: usize, count: usize) -> Rect {
+	let columns = (count.max(1) as f32).sqrt().ceil() as usize;
+	let rows = count.max(1).div_ceil(columns);
+	let size = Vec2::new(1.0 / columns as f32, 1.0 / rows as f32);
+	let min = Vec2::new((index % columns) as f32, (index / columns) as f32) * size;
+	Rect::from_corners(min, min + size)
+}
+
+/// Where in the split screen a local player's view goes, as its index and the number of views, `None` for players not on this machine
+pub fn split_screen_slot(player: Entity, local_players: impl IntoIterator<Item = Entity>) -> Option<(usize, usize)> {
+	let mut local_players: Vec<Entity> = local_players.into_iter().collect();
+	// Ordered by entity, so views don't swap places as players come and go
+	local_players.sort();
+	let index = local_players.iter().position(|local_player| *local_player == player)?;
+	Some((index, local_players.len()))
+}
+
+/// How a `CameraRig` places its camera
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum CameraMode {
+	/// Looking out of the player's eyes
+	#[default]
+	FirstPerson,
+	/// Following behind the player, pulled in when level geometry is in the way
+	ThirdPerson,
+	/// Flying around freely, the player is left alone
+	Spectator,
+}
+
+impl CameraMode {
+	/// The mode switching modes leads to
+	pub fn next(self) -> Self {
+		match self {
+			CameraMode::FirstPerson => CameraMode::ThirdPerson,
+			CameraMode::ThirdPerson => CameraMode::Spectator,
+			CameraMode::Spectator => CameraMode::FirstPerson,
+		}
+	}
+}
+
+/// The camera watching a player, placed according to its mode from the player's `PlayerCamera` view
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct CameraRig {
+	/// Player entity this rig is watching
+	pub player: Entity,
+	pub mode: CameraMode,
+	/// Where the spectator camera is, only used while spectating
+	spectator_position: Vec3,
+	/// Angle around the vertical axis of the spectator camera [radian]
+	spectator_yaw: f32,
+	/// Angle between -pi/2 and +pi/2 measuring the pitch of the spectator camera
+	spectator_pitch: f32,
+}
+
+impl CameraRig {
+	pub fn new(player: Entity) -> Self {
+		Self {
+			player,
+			mode: CameraMode::default(),
+			spectator_position: Vec3::ZERO,
+			spectator_yaw: 0.0,
+			spectator_pitch: 0.0,
+		}
+	}
+
+	/// Turns the spectator camera by a yaw (x) and pitch (y) [radian]
+	pub fn rotate_spectator(&mut self, delta_angle: Vec2) {
+		self.spectator_yaw -= delta_angle.x;
+		self.spectator_pitch = f32::clamp(self.spectator_pitch + delta_angle.y, -PI/2.0, PI/2.0);
+	}
+
+	fn spectator_rotation(&self) -> Quat {
+		Quat::from_euler(EulerRot::YXZ, self.spectator_yaw, self.spectator_pitch, 0.0)
+	}
+
+	/// Starts spectating from wherever the camera currently is
+	fn start_spectating(&mut self, camera_transform: &Transform) {
+		let (yaw, pitch, _) = camera_transform.rotation.to_euler(EulerRot::YXZ);
+		self.spectator_position = camera_transform.translation;
+		self.spectator_yaw = yaw;
+		self.spectator_pitch = pitch;
+	}
+}
+
+impl Plugin for CameraRigPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<CameraRigSettings>()
+			.add_systems(FixedUpdate, (
+				switch_camera_mode.before(process_mouse_movement),
+				fly_spectator.after(rotate_player),
+			).after(SamplePlayerInput))
+			.add_systems(PostUpdate, place_camera_rigs
+				.after(interpolate_rendered_transform)
+				.before(TransformSystem::TransformPropagate))
+			.add_systems(Update, split_screen_between_rigs)
+		;
+	}
+}
+
+fn switch_camera_mode(
+	mut rigs: Query<(&Transform, &mut CameraRig)>,
+	players: Query<&PlayerInput, With<Player>>,
+) {
+	for (transform, mut rig) in rigs.iter_mut() {
+		if !players.get(rig.player).is_ok_and(|player_input| player_input.camera_mode_pressed) {continue;}
+		rig.mode = rig.mode.next();
+		if rig.mode == CameraMode::Spectator {
+			rig.start_spectating(transform);
+		}
+	}
+}
+
+fn fly_spectator(
+	mut rigs: Query<&mut CameraRig>,
+	players: Query<&PlayerInput, With<Player>>,
+	time: Res<Time>,
+) {
+	let dt = time.delta_seconds();
+	for mut rig in rigs.iter_mut() {
+		if rig.mode != CameraMode::Spectator {continue;}
+		let Ok(player_input) = players.get(rig.player) else {continue;};
+		let input = player_input.spectator_move.clamp_length_max(1.0);
+		let direction = rig.spectator_rotation() * Vec3::new(input.x, 0.0, -input.y);
+		rig.spectator_position += SPECTATOR_SPEED * dt * direction;
+	}
+}
+
+/// Moves every camera to where its mode wants it, from the rendered (interpolated) transform of its player
+fn place_camera_rigs(
+	mut rigs: Query<(&mut Transform, &CameraRig), Without<Player>>,
+	players: Query<(&Transform, &PlayerMovement), With<Player>>,
+	views: Query<(&Transform, &Parent), (With<PlayerCamera>, Without<Player>, Without<CameraRig>)>,
+	rapier_context: Res<RapierContext>,
+	settings: Res<CameraRigSettings>,
+) {
+	for (mut transform, rig) in rigs.iter_mut() {
+		if rig.mode == CameraMode::Spectator {
+			*transform = Transform::from_translation(rig.spectator_position).with_rotation(rig.spectator_rotation());
+			continue;
+		}
+		let Ok((player_transform, movement)) = players.get(rig.player) else {continue;};
+		let Some((view_transform, _)) = views.iter().find(|(_, parent)| parent.get() == rig.player) else {continue;};
+		let eyes = player_transform.mul_transform(*view_transform);
+		let filter = QueryFilter::default().exclude_collider(rig.player).exclude_sensors();
+		let radius = settings.collision_radius;
+
+		// The eyes can end up in a ceiling or a wall at speed, the middle of the player never can
+		let look_ahead = settings.look_ahead_offset(movement.desired_velocity + movement.ground_velocity, movement.up);
+		let eye_position = solve_camera_collision(&rapier_context, player_transform.translation, eyes.translation + look_ahead, radius, filter);
+
+		let position = match rig.mode {
+			// Pull the camera in front of whatever is between it and the player, so it never looks through walls
+			CameraMode::ThirdPerson => solve_camera_collision(&rapier_context, eye_position, eye_position + CHASE_DISTANCE * eyes.back(), radius, filter),
+			_ => eye_position,
+		};
+		*transform = eyes.with_translation(position);
+	}
+}
+
+/// Gives every local player's camera its own part of the window, a single player gets all of it
+fn split_screen_between_rigs(
+	mut rigs: Query<(&CameraRig, &mut Camera, &mut Camera3d)>,
+	local_players: Query<Entity, (With<Player>, With<LocalInput>)>,
+	windows: Query<&Window, With<PrimaryWindow>>,
+) {
+	let Ok(window) = windows.get_single() else {return;};
+	let window_size = Vec2::new(window.physical_width() as f32, window.physical_height() as f32);
+	for (rig, mut camera, mut camera_3d) in rigs.iter_mut() {
+		let Some((index, count)) = split_screen_slot(rig.player, local_players.iter()) else {continue;};
+		let viewport = (count > 1).then(|| {
+			let rect = split_screen_rect(index, count);
+			let min = (rect.min * window_size).as_uvec2();
+			Viewport {
+				physical_position: min,
+				physical_size: (rect.max * window_size).as_uvec2() - min,
+				..default()
+			}
+		});
+		let order = index as isize;
+		// Clearing always clears the whole window, only the first view may do it
+		let clears = index == 0;
+
+		let viewport_changed = camera.viewport.as_ref().map(|viewport| (viewport.physical_position, viewport.physical_size))
+			!= viewport.as_ref().map(|viewport| (viewport.physical_position, viewport.physical_size));
+		if viewport_changed || camera.order != order {
+			camera.viewport = viewport;
+			camera.order = order;
+		}
+		if clears == matches!(camera_3d.clear_color, ClearColorConfig::None) {
+			camera_3d.clear_color = if clears {ClearColorConfig::Default} else {ClearColorConfig::None};
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing;
+
+	#[test]
+	fn camera_stops_in_front_of_wall() {
+		let mut app = testing::simulation_app();
+		let wall_surface = 9.5;
+		app.world.spawn((
+			TransformBundle::from_transform(Transform::from_xyz(wall_surface + 0.5, 0.0, 0.0)),
+			RigidBody::Fixed,
+			Collider::cuboid(0.5, 5.0, 5.0),
+		));
+		// The wall only shows up in scene queries once the physics engine has stepped with it
+		testing::step(&mut app);
+
+		let radius = CameraRigSettings::default().collision_radius;
+		let rapier_context = app.world.resource::<RapierContext>();
+		let pivot = Vec3::new(0.0, 1.0, 0.0);
+		let camera = solve_camera_collision(rapier_context, pivot, Vec3::new(20.0, 1.0, 0.0), radius, QueryFilter::default());
+		let expected = wall_surface - radius - CAMERA_COLLISION_MARGIN;
+		assert!((camera.x - expected).abs() < 1e-3, "camera at {camera}, expected x = {expected}");
+		assert_eq!((camera.y, camera.z), (pivot.y, pivot.z));
+
+		let short_of_wall = Vec3::new(5.0, 1.0, 0.0);
+		assert_eq!(solve_camera_collision(rapier_context, pivot, short_of_wall, radius, QueryFilter::default()), short_of_wall);
+	}
+
+	#[test]
+	fn look_ahead_is_clamped_and_horizontal() {
+		let settings = CameraRigSettings {look_ahead: true, ..Default::default()};
+		let fast = Vec3::new(1000.0, 0.0, 0.0);
+		assert!((settings.look_ahead_offset(fast, Vec3::Y).length() - settings.max_look_ahead).abs() < 1e-5);
+
+		let slow = Vec3::new(0.0, 0.0, 5.0);
+		assert_eq!(settings.look_ahead_offset(slow, Vec3::Y), settings.look_ahead_per_speed * slow);
+		assert_eq!(settings.look_ahead_offset(slow + Vec3::new(0.0, -50.0, 0.0), Vec3::Y), settings.look_ahead_offset(slow, Vec3::Y));
+		assert_eq!(settings.look_ahead_offset(Vec3::new(0.0, 1000.0, 0.0), Vec3::Y), Vec3::ZERO);
+
+		let disabled = CameraRigSettings::default();
+		assert_eq!(disabled.look_ahead_offset(fast, Vec3::Y), Vec3::ZERO);
+	}
+
+	#[test]
+	fn split_screen_tiles_the_window() {
+		assert_eq!(split_screen_rect(0, 1), Rect::new(0.0, 0.0, 1.0, 1.0));
+		assert_eq!(split_screen_rect(1, 2), Rect::new(0.5, 0.0, 1.0, 1.0));
+		assert_eq!(split_screen_rect(2, 3), Rect::new(0.0, 0.5, 0.5, 1.0));
+		for count in 1..=4 {
+			let area: f32 = (0..count).map(|index| {
+				let size = split_screen_rect(index, count).size();
+				size.x * size.y
+			}).sum();
+			assert!(area <= 1.0 + 1e-6, "{count} views cover {area} of the window");
+		}
+	}
+}
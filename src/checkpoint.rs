@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::input::{PlayerInput, SamplePlayerInput};
+use crate::integrity::PlayerIntegrity;
+use crate::marble::Marble;
+use crate::player::{MovementStateChanged, Player, PlayerCamera, PlayerInterpolation, PlayerMovement, standing_collider, restore_simulated_transform};
+
+pub struct CheckpointPlugin;
+
+/// Marks a sensor collider that sets the respawn point of players passing through it
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct Checkpoint;
+
+/// Marks a sensor collider that respawns players touching it
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct KillVolume;
+
+/// Players falling below this height get respawned
+#[derive(Resource, Clone, Copy, PartialEq, Debug)]
+pub struct KillPlane {
+	pub height: f32,
+}
+
+impl Default for KillPlane {
+	fn default() -> Self {
+		Self {
+			height: -100.0,
+		}
+	}
+}
+
+/// Where a player gets put back when respawning
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
+pub struct RespawnPoint {
+	pub transform: Transform,
+	/// Checkpoint the respawn point comes from, `None` for the place the player first spawned at
+	pub checkpoint: Option<Entity>,
+}
+
+/// Sent after a player has been put back at their respawn point
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PlayerRespawned {
+	pub player: Entity,
+	pub checkpoint: Option<Entity>,
+}
+
+impl Plugin for CheckpointPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_resource::<KillPlane>()
+			.add_event::<PlayerRespawned>()
+			.add_systems(FixedUpdate, update_checkpoints.after(SamplePlayerInput).before(restore_simulated_transform))
+		;
+	}
+}
+
+/// Moves respawn points to touched checkpoints and respawns players that asked for it or are out of bounds
+pub(crate) fn update_checkpoints(
+	mut players: Query<(
+		Entity, &PlayerInput, &mut RespawnPoint, &mut Transform, &mut PlayerInterpolation, &mut PlayerMovement, &mut Collider,
+		Option<&mut Velocity>, Option<&PlayerIntegrity>, Has<Marble>,
+	), With<Player>>,
+	mut cameras: Query<(&mut Transform, &mut PlayerCamera, &Parent), Without<Player>>,
+	checkpoints: Query<&Transform, (With<Checkpoint>, Without<Player>, Without<PlayerCamera>)>,
+	kill_volumes: Query<(), With<KillVolume>>,
+	rapier_context: Res<RapierContext>,
+	kill_plane: Res<KillPlane>,
+	mut respawn_events: EventWriter<PlayerRespawned>,
+	mut state_events: EventWriter<MovementStateChanged>,
+) {
+	for (
+		player, player_input, mut respawn_point, mut transform, mut interpolation, mut movement, mut collider, velocity, integrity, is_marble,
+	) in players.iter_mut() {
+		// This runs before the simulated transform is restored, `transform` is still the rendered one
+		let simulated = interpolation.current;
+		let mut touched_checkpoint = None;
+		let mut touched_kill_volume = false;
+		rapier_context.intersections_with_shape(
+			simulated.translation,
+			simulated.rotation,
+			&collider,
+			QueryFilter::default().exclude_solids().exclude_collider(player),
+			|sensor| {
+				if checkpoints.contains(sensor) {
+					touched_checkpoint = Some(sensor);
+				}
+				touched_kill_volume |= kill_volumes.contains(sensor);
+				true
+			},
+		);
+
+		if let Some(checkpoint) = touched_checkpoint {
+			if let Ok(checkpoint_transform) = checkpoints.get(checkpoint) {
+				*respawn_point = RespawnPoint { transform: *checkpoint_transform, checkpoint: Some(checkpoint) };
+			}
+		}
+
+		let out_of_bounds = touched_kill_volume || simulated.translation.y < kill_plane.height;
+		let destroyed = integrity.is_some_and(|integrity| integrity.ready_to_respawn());
+		if !out_of_bounds && !destroyed && !player_input.respawn_pressed {continue;}
+
+		*transform = respawn_point.transform;
+		// Teleport, don't let the rendered transform slide over from where we were
+		interpolation.previous = respawn_point.transform;
+		interpolation.current = respawn_point.transform;
+		let respawned = PlayerMovement::default();
+		// Leave the old state the usual way first, so listeners hear about it
+		let state_change = movement.transition(player, respawned.state);
+		*movement = respawned;
+		if let Some(event) = state_change {
+			state_events.send(event);
+		}
+		if !is_marble {
+			*collider = standing_collider();
+		}
+		if let Some(mut velocity) = velocity {
+			*velocity = Velocity::zero();
+		}
+		for (mut camera_transform, mut camera, parent) in cameras.iter_mut() {
+			if parent.get() == player {
+				camera.reset(&mut camera_transform);
+			}
+		}
+		respawn_events.send(PlayerRespawned { player, checkpoint: respawn_point.checkpoint });
+	}
+}
@@ -0,0 +1,188 @@
+use bevy::prelude::*;
+use bevy_rapier3d::{prelude::*, utils::iso_to_transform};
+
+use crate::checkpoint::{PlayerRespawned, update_checkpoints};
+use crate::input::PlayerInput;
+use crate::marble::drive_marble;
+use crate::player::{Player, PlayerCamera, PlayerMovement, find_eye_and_view, player_dash, player_kinematics, player_mass};
+use crate::tuning::MovementTuning;
+
+pub struct GrapplePlugin;
+
+/// Grappling hook state of a player
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
+pub struct Grapple {
+	/// What the rope is currently attached to, if anything
+	pub attachment: Option<GrappleAttachment>,
+	/// How much longer until the grapple can be fired again
+	pub cooldown: f32,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GrappleAttachment {
+	/// Rigid body the rope is attached to, or the collider that was hit if it has no body
+	pub target: Entity,
+	/// Attachment point in the local space of `target`, so the anchor moves along with it
+	pub local_anchor: Vec3,
+	/// Length of the rope, the player can get closer to the anchor but not further away
+	pub rope_length: f32,
+}
+
+/// Sent when a grapple hooks onto something
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GrappleAttached {
+	pub player: Entity,
+	pub target: Entity,
+}
+
+/// Sent when a grapple lets go of whatever it was attached to
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GrappleDetached {
+	pub player: Entity,
+	pub target: Entity,
+}
+
+impl Plugin for GrapplePlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_event::<GrappleAttached>()
+			.add_event::<GrappleDetached>()
+			.add_systems(FixedUpdate, (
+				fire_grapple,
+				reel_grapple,
+				apply_rope_constraint,
+			).chain().after(player_dash).before(drive_marble).before(player_kinematics))
+			.add_systems(FixedUpdate, detach_on_respawn.after(update_checkpoints))
+		;
+	}
+}
+
+/// Where the physics engine has a body, or a collider that isn't attached to one, in world space
+fn world_pose(rapier_context: &RapierContext, entity: Entity) -> Option<Transform> {
+	let scale = rapier_context.physics_scale();
+	if let Some(body) = rapier_context.entity2body().get(&entity).and_then(|handle| rapier_context.bodies.get(*handle)) {
+		return Some(iso_to_transform(body.position(), scale));
+	}
+	let collider = rapier_context.entity2collider().get(&entity).and_then(|handle| rapier_context.colliders.get(*handle))?;
+	Some(iso_to_transform(collider.position(), scale))
+}
+
+/// Attaches the grapple to whatever the player is looking at, or detaches it if it's already attached
+fn fire_grapple(
+	mut players: Query<(Entity, &Transform, &PlayerInput, &mut Grapple), With<Player>>,
+	cameras: Query<(&Transform, &PlayerCamera, &Parent), Without<Player>>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+	mut attached_events: EventWriter<GrappleAttached>,
+	mut detached_events: EventWriter<GrappleDetached>,
+) {
+	for (player, player_transform, player_input, mut grapple) in players.iter_mut() {
+		grapple.cooldown = f32::max(grapple.cooldown - time.delta_seconds(), 0.0);
+		if !player_input.grapple_pressed {continue;}
+
+		if let Some(attachment) = grapple.attachment.take() {
+			grapple.cooldown = tuning.grapple_cooldown;
+			detached_events.send(GrappleDetached { player, target: attachment.target });
+			continue;
+		}
+		if grapple.cooldown > 0.0 {continue;}
+
+		let Some((eye, view_direction)) = find_eye_and_view(player, player_transform, cameras.iter()) else {continue;};
+		let Some((collider, toi)) = rapier_context.cast_ray(
+			eye,
+			view_direction,
+			tuning.grapple_range,
+			true,
+			QueryFilter::default().exclude_collider(player).exclude_sensors(),
+		) else {continue;};
+		// Hang on to the body rather than the collider, that's what moves and can be pulled
+		let target = rapier_context.collider_parent(collider).unwrap_or(collider);
+		let Some(target_pose) = world_pose(&rapier_context, target) else {continue;};
+
+		let anchor = eye + toi * view_direction;
+		grapple.attachment = Some(GrappleAttachment {
+			target,
+			local_anchor: target_pose.compute_affine().inverse().transform_point3(anchor),
+			rope_length: toi,
+		});
+		attached_events.send(GrappleAttached { player, target });
+	}
+}
+
+fn detach_on_respawn(
+	mut respawn_events: EventReader<PlayerRespawned>,
+	mut players: Query<&mut Grapple>,
+	mut detached_events: EventWriter<GrappleDetached>,
+) {
+	for respawn in respawn_events.read() {
+		let Ok(mut grapple) = players.get_mut(respawn.player) else {continue;};
+		if let Some(attachment) = grapple.attachment.take() {
+			detached_events.send(GrappleDetached { player: respawn.player, target: attachment.target });
+		}
+	}
+}
+
+fn reel_grapple(
+	mut players: Query<(&PlayerInput, &mut Grapple), With<Player>>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+) {
+	for (player_input, mut grapple) in players.iter_mut() {
+		let mut reel_direction = 0.0;
+		if player_input.grapple_reel_in_held {reel_direction -= 1.0;}
+		if player_input.grapple_reel_out_held {reel_direction += 1.0;}
+		if reel_direction == 0.0 {continue;}
+
+		if let Some(attachment) = grapple.attachment.as_mut() {
+			attachment.rope_length = f32::clamp(
+				attachment.rope_length + reel_direction * tuning.grapple_reel_speed * time.delta_seconds(),
+				tuning.grapple_min_length,
+				tuning.grapple_range,
+			);
+		}
+	}
+}
+
+/// Keeps the player within rope length of the anchor, letting them swing around it,
+/// and pulls dynamic bodies the rope is attached to
+fn apply_rope_constraint(
+	mut players: Query<(Entity, &Transform, &mut PlayerMovement, &mut Grapple, &ColliderMassProperties), With<Player>>,
+	mut targets: Query<(Option<&RigidBody>, Option<&mut ExternalImpulse>), Without<Player>>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+	mut detached_events: EventWriter<GrappleDetached>,
+) {
+	for (player, transform, mut movement, mut grapple, mass_properties) in players.iter_mut() {
+		let Some(attachment) = grapple.attachment else {continue;};
+		let (Some(target_pose), Ok((rigid_body, external_impulse))) = (world_pose(&rapier_context, attachment.target), targets.get_mut(attachment.target)) else {
+			// Whatever we were attached to is gone
+			grapple.attachment = None;
+			grapple.cooldown = tuning.grapple_cooldown;
+			detached_events.send(GrappleDetached { player, target: attachment.target });
+			continue;
+		};
+
+		let anchor = target_pose.transform_point(attachment.local_anchor);
+		let offset = transform.translation - anchor;
+		let distance = offset.length();
+		if distance < attachment.rope_length || distance < 0.001 {continue;}
+
+		// The rope only pulls, so only the velocity going away from the anchor gets removed
+		let rope_direction = offset / distance;
+		let outward_speed = f32::max(movement.desired_velocity.dot(rope_direction), 0.0);
+		movement.desired_velocity -= outward_speed * rope_direction;
+		// Pull back whatever the rope got stretched by
+		let correction_speed = tuning.grapple_stiffness * (distance - attachment.rope_length);
+		movement.desired_velocity -= correction_speed * rope_direction;
+
+		if rigid_body == Some(&RigidBody::Dynamic) {
+			// Hand the momentum the rope took from the player over to the body
+			let impulse = tuning.grapple_pull_strength * player_mass(mass_properties) * (outward_speed + correction_speed) * rope_direction;
+			// Added to rather than inserted, several players can be pulling on the same body
+			if let Some(mut external_impulse) = external_impulse {
+				external_impulse.impulse += impulse;
+			}
+		}
+	}
+}
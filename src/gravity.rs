@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::platform::follow_ground_body;
+use crate::player::{Player, PlayerMovement, detect_ground};
+use crate::tuning::MovementTuning;
+
+pub struct GravityPlugin;
+
+/// Marks a sensor collider inside of which gravity pulls players and dynamic bodies in another direction
+///
+/// Only the direction changes, the strength is still that of `RapierConfiguration::gravity` times the `GravityScale`
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct GravityZone {
+	/// Direction gravity pulls in while inside, doesn't need to be normalized
+	pub direction: Vec3,
+}
+
+/// Marks a solid collider that pulls players standing on it towards its surface, for loops and half-pipes
+///
+/// Takes precedence over any `GravityZone`, as soon as the player leaves the surface the usual gravity takes over again,
+/// props are not affected and fall the usual way
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct SurfaceGravity;
+
+impl Plugin for GravityPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(FixedUpdate, (
+				update_player_gravity,
+				align_players_to_up,
+			).chain().after(detect_ground).before(follow_ground_body))
+			.add_systems(FixedUpdate, redirect_gravity_in_zones.before(PhysicsSet::SyncBackend))
+		;
+	}
+}
+
+/// Gravity the physics engine applies to a body with the given scale
+pub fn body_gravity(rapier_config: &RapierConfiguration, gravity_scale: Option<&GravityScale>) -> Vec3 {
+	gravity_scale.map_or(1.0, |scale| scale.0) * rapier_config.gravity
+}
+
+/// Direction of the first `GravityZone` a collider at the given position overlaps, if any
+fn zone_direction(
+	rapier_context: &RapierContext,
+	zones: &Query<&GravityZone>,
+	entity: Entity,
+	transform: &Transform,
+	collider: &Collider,
+) -> Option<Vec3> {
+	let mut direction = None;
+	rapier_context.intersections_with_shape(
+		transform.translation,
+		transform.rotation,
+		collider,
+		QueryFilter::default().exclude_solids().exclude_collider(entity),
+		|sensor| {
+			direction = zones.get(sensor).ok()
+				.and_then(|zone| zone.direction.try_normalize());
+			// Stop at the first zone, overlapping zones are up to the level to avoid
+			direction.is_none()
+		},
+	);
+	direction
+}
+
+/// Picks the gravity of every player, its strength comes from the physics configuration,
+/// its direction from the surface they stand on, the zone they are in or the world, in that order
+fn update_player_gravity(
+	mut players: Query<(Entity, &Transform, &Collider, Option<&GravityScale>, &mut PlayerMovement), With<Player>>,
+	zones: Query<&GravityZone>,
+	surfaces: Query<(), With<SurfaceGravity>>,
+	rapier_context: Res<RapierContext>,
+	rapier_config: Res<RapierConfiguration>,
+) {
+	for (entity, transform, collider, gravity_scale, mut player) in players.iter_mut() {
+		let world_gravity = body_gravity(&rapier_config, gravity_scale);
+		player.gravity = world_gravity.length();
+
+		let on_gravity_surface = player.ground_collider.is_some_and(|ground| {
+			surfaces.contains(ground) || rapier_context.collider_parent(ground).is_some_and(|body| surfaces.contains(body))
+		});
+		if let Some(ground_normal) = player.ground_normal.filter(|_| on_gravity_surface) {
+			player.up = ground_normal;
+			continue;
+		}
+
+		let direction = zone_direction(&rapier_context, &zones, entity, transform, collider)
+			.or_else(|| world_gravity.try_normalize());
+		// Without any gravity there is no up, keep whichever we had
+		if let Some(direction) = direction {
+			player.up = -direction;
+		}
+	}
+}
+
+/// Turns the gravity the physics engine applies to dynamic bodies inside of a `GravityZone` towards the zone's direction
+fn redirect_gravity_in_zones(
+	mut commands: Commands,
+	mut bodies: Query<(Entity, &Transform, &Collider, &RigidBody, Option<&GravityScale>, Option<&mut ExternalImpulse>), Without<Player>>,
+	zones: Query<&GravityZone>,
+	rapier_context: Res<RapierContext>,
+	rapier_config: Res<RapierConfiguration>,
+	time: Res<Time>,
+) {
+	let dt = time.delta_seconds();
+	for (entity, transform, collider, rigid_body, gravity_scale, external_impulse) in bodies.iter_mut() {
+		if *rigid_body != RigidBody::Dynamic {continue;}
+		let Some(direction) = zone_direction(&rapier_context, &zones, entity, transform, collider) else {continue;};
+		let Some(body) = rapier_context.entity2body().get(&entity).and_then(|handle| rapier_context.bodies.get(*handle)) else {continue;};
+		let world_gravity = body_gravity(&rapier_config, gravity_scale);
+		// Make up for the difference between what the physics engine applies and what the zone wants
+		let impulse = body.mass() * dt * (world_gravity.length() * direction - world_gravity);
+		match external_impulse {
+			Some(mut external_impulse) => external_impulse.impulse += impulse,
+			None => {
+				commands.entity(entity).insert(ExternalImpulse { impulse, ..Default::default() });
+			},
+		}
+	}
+}
+
+/// Turns players' transforms (and with them their cameras) towards their up direction, a little bit every tick
+fn align_players_to_up(
+	mut players: Query<(&mut Transform, &PlayerMovement), With<Player>>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+) {
+	let fraction = f32::min(tuning.up_alignment_rate * time.delta_seconds(), 1.0);
+	for (mut transform, player) in players.iter_mut() {
+		let current_up = transform.up();
+		if current_up.angle_between(player.up) < 0.001 {continue;}
+		let alignment = Quat::from_rotation_arc(current_up, player.up);
+		transform.rotation = (Quat::IDENTITY.slerp(alignment, fraction) * transform.rotation).normalize();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing;
+
+	#[test]
+	fn props_fall_like_idle_players() {
+		let mut app = testing::simulation_app();
+		let player = testing::enter_level(&mut app);
+		// Props have no air drag, without it players fall the way the physics engine lets bodies fall
+		app.insert_resource(MovementTuning {air_drag: 0.0, ..Default::default()});
+		testing::place_player(&mut app, player, Vec3::new(0.0, 200.0, 0.0));
+		let prop = app.world.spawn((
+			TransformBundle::from_transform(Transform::from_xyz(10.0, 200.0, 0.0)),
+			RigidBody::Dynamic, Velocity::zero(), GravityScale(1.0), Sleeping::disabled(),
+			Collider::cuboid(0.5, 0.5, 0.5), ColliderMassProperties::Mass(5.0),
+		)).id();
+
+		let ticks = 300;
+		for _ in 0..ticks {
+			testing::step(&mut app);
+		}
+		let player_speed = app.world.get::<PlayerMovement>(player).unwrap().desired_velocity.y;
+		let prop_speed = app.world.get::<Velocity>(prop).unwrap().linvel.y;
+		let free_fall_speed = -9.81 * ticks as f32 * Time::<Fixed>::default().timestep().as_secs_f32();
+		// Either of them may start falling a tick later than the other
+		let tolerance = 1.5 * 9.81 * Time::<Fixed>::default().timestep().as_secs_f32();
+		assert!((prop_speed - free_fall_speed).abs() < tolerance, "prop at {prop_speed} m/s, free fall at {free_fall_speed} m/s");
+		assert!((player_speed - prop_speed).abs() < tolerance, "player at {player_speed} m/s, prop at {prop_speed} m/s");
+	}
+}
@@ -1,4 +1,6 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, input::{mouse::MouseMotion, InputSystem}, utils::HashMap, window::{CursorGrabMode, PrimaryWindow}};
+
+use crate::state::GameState;
 
 pub struct InputPlugin;
 
@@ -7,13 +9,78 @@ pub struct InputPlugin;
 pub struct InputMap {
 	pub terminal_key: KeyCode,
 	pub velocity_lock: KeyCode,
+	pub jump: KeyCode,
+	pub crouch: KeyCode,
+	pub grapple: KeyCode,
+	pub grapple_reel_in: KeyCode,
+	pub grapple_reel_out: KeyCode,
+	pub dash: KeyCode,
+	pub respawn: KeyCode,
+	pub camera_mode: KeyCode,
+	pub lock_on: KeyCode,
+	pub lock_on_cycle: KeyCode,
+	pub spectator_forward: KeyCode,
+	pub spectator_back: KeyCode,
+	pub spectator_left: KeyCode,
+	pub spectator_right: KeyCode,
 }
 
 impl Default for InputMap {
 	fn default() -> Self {
 		Self {
 			terminal_key: KeyCode::T,
-			velocity_lock: KeyCode::ShiftLeft
+			velocity_lock: KeyCode::ShiftLeft,
+			jump: KeyCode::Space,
+			crouch: KeyCode::ControlLeft,
+			grapple: KeyCode::E,
+			grapple_reel_in: KeyCode::R,
+			grapple_reel_out: KeyCode::F,
+			dash: KeyCode::Q,
+			respawn: KeyCode::X,
+			camera_mode: KeyCode::V,
+			lock_on: KeyCode::G,
+			lock_on_cycle: KeyCode::Tab,
+			spectator_forward: KeyCode::W,
+			spectator_back: KeyCode::S,
+			spectator_left: KeyCode::A,
+			spectator_right: KeyCode::D,
+		}
+	}
+}
+
+#[derive(Resource, Debug, PartialEq)]
+/// The gamepad counterpart of `InputMap`, the right stick looks around and the left one flies the spectator camera
+pub struct GamepadMap {
+	pub velocity_lock: GamepadButtonType,
+	pub jump: GamepadButtonType,
+	pub crouch: GamepadButtonType,
+	pub grapple: GamepadButtonType,
+	pub grapple_reel_in: GamepadButtonType,
+	pub grapple_reel_out: GamepadButtonType,
+	pub dash: GamepadButtonType,
+	pub respawn: GamepadButtonType,
+	pub camera_mode: GamepadButtonType,
+	pub lock_on: GamepadButtonType,
+	pub lock_on_cycle: GamepadButtonType,
+	/// Joins the level as another local player, on a gamepad no player is using yet
+	pub join: GamepadButtonType,
+}
+
+impl Default for GamepadMap {
+	fn default() -> Self {
+		Self {
+			velocity_lock: GamepadButtonType::LeftTrigger2,
+			jump: GamepadButtonType::South,
+			crouch: GamepadButtonType::East,
+			grapple: GamepadButtonType::RightTrigger2,
+			grapple_reel_in: GamepadButtonType::RightTrigger,
+			grapple_reel_out: GamepadButtonType::LeftTrigger,
+			dash: GamepadButtonType::West,
+			respawn: GamepadButtonType::Select,
+			camera_mode: GamepadButtonType::North,
+			lock_on: GamepadButtonType::RightThumb,
+			lock_on_cycle: GamepadButtonType::DPadRight,
+			join: GamepadButtonType::Start,
 		}
 	}
 }
@@ -23,22 +90,321 @@ impl Default for InputMap {
 pub struct InputSettings {
 	// TODO: Move all configurable input values (ex. sensitivity) to here
 	/// Camera sensitivity/turning speed [radian/mouse_unit]
-	pub camera_sensitivity: f32
+	pub camera_sensitivity: f32,
+	/// Whether momentum lock is only engaged while its key is held, instead of being toggled by it
+	pub hold_to_lock: bool,
+	/// Mouse units a fully tilted right stick stands in for every second [mouse_unit/s]
+	pub gamepad_look_speed: f32,
 }
 
 impl Default for InputSettings {
 	fn default() -> Self {
 		Self {
-			camera_sensitivity: 0.005
+			camera_sensitivity: 0.005,
+			hold_to_lock: false,
+			gamepad_look_speed: 600.0,
+		}
+	}
+}
+
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+/// Tracks who owns the mouse cursor, the game (locked and hidden) or the user (free)
+pub struct CursorGrab {
+	/// Whether the cursor is currently locked to the window and hidden, mouse motion should only steer the player while this is set
+	pub grabbed: bool,
+	/// Set by UI (terminal, menus) when it wants the cursor released
+	pub released_by_ui: bool,
+}
+
+/// Everything the player simulation gets to know about a player's inputs during a single fixed tick,
+/// the same stream of these always produces the same simulation
+///
+/// Every player reads its own component, filled in by whatever controls that player (see `LocalInput`)
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
+pub struct PlayerInput {
+	/// Mouse movement since the previous tick, zero while the cursor is not grabbed
+	pub mouse_delta: Vec2,
+	pub velocity_lock_pressed: bool,
+	pub velocity_lock_held: bool,
+	pub jump_pressed: bool,
+	pub jump_held: bool,
+	pub crouch_held: bool,
+	pub grapple_pressed: bool,
+	pub grapple_reel_in_held: bool,
+	pub grapple_reel_out_held: bool,
+	pub dash_pressed: bool,
+	pub respawn_pressed: bool,
+	pub camera_mode_pressed: bool,
+	pub lock_on_pressed: bool,
+	/// Moves the lock-on over to the next target in reach
+	pub lock_on_cycle_pressed: bool,
+	/// Target picked from the target list, picking the current target again lets go of it
+	pub lock_on_select: Option<Entity>,
+	/// Direction a free spectator camera is asked to fly in, x to the right and y forwards
+	pub spectator_move: Vec2,
+}
+
+impl PlayerInput {
+	/// Folds the input of another frame into this one, presses are kept until consumed by a tick
+	fn accumulate(&mut self, frame: &PlayerInput) {
+		self.mouse_delta += frame.mouse_delta;
+		self.velocity_lock_pressed |= frame.velocity_lock_pressed;
+		self.jump_pressed |= frame.jump_pressed;
+		self.grapple_pressed |= frame.grapple_pressed;
+		self.dash_pressed |= frame.dash_pressed;
+		self.respawn_pressed |= frame.respawn_pressed;
+		self.camera_mode_pressed |= frame.camera_mode_pressed;
+		self.lock_on_pressed |= frame.lock_on_pressed;
+		self.lock_on_cycle_pressed |= frame.lock_on_cycle_pressed;
+		self.lock_on_select = frame.lock_on_select.or(self.lock_on_select);
+		self.velocity_lock_held = frame.velocity_lock_held;
+		self.jump_held = frame.jump_held;
+		self.crouch_held = frame.crouch_held;
+		self.grapple_reel_in_held = frame.grapple_reel_in_held;
+		self.grapple_reel_out_held = frame.grapple_reel_out_held;
+		self.spectator_move = frame.spectator_move;
+	}
+
+	/// What is left of the input for the following tick, once this one has been consumed
+	fn consumed(&self) -> PlayerInput {
+		PlayerInput {
+			velocity_lock_held: self.velocity_lock_held,
+			jump_held: self.jump_held,
+			crouch_held: self.crouch_held,
+			grapple_reel_in_held: self.grapple_reel_in_held,
+			grapple_reel_out_held: self.grapple_reel_out_held,
+			spectator_move: self.spectator_move,
+			..Default::default()
 		}
 	}
 }
 
+/// Something on this machine a player can be controlled with
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum InputSource {
+	#[default]
+	KeyboardMouse,
+	Gamepad(Gamepad),
+}
+
+/// Marks players controlled from this machine, their `PlayerInput` is filled in from their input source every tick
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct LocalInput {
+	pub source: InputSource,
+}
+
+/// Fixed tick system set refreshing `PlayerInput`, anything reading it should run after this
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct SamplePlayerInput;
+
+/// Input gathered from rendered frames that hasn't been handed to a fixed tick yet, for every input source
+#[derive(Resource, Clone, PartialEq, Debug, Default)]
+pub struct PendingPlayerInput(HashMap<InputSource, PlayerInput>);
+
+impl PendingPlayerInput {
+	/// Folds input from outside of the input devices, like clicks on the HUD, into what a source did this frame
+	pub fn accumulate(&mut self, source: InputSource, frame: &PlayerInput) {
+		self.0.entry(source).or_default().accumulate(frame);
+	}
+}
+
 impl Plugin for InputPlugin {
 	fn build(&self, app: &mut App) {
 		app
 		.init_resource::<InputMap>()
+		.init_resource::<GamepadMap>()
 		.init_resource::<InputSettings>()
+		.init_resource::<CursorGrab>()
+		.init_resource::<PendingPlayerInput>()
+		.add_systems(PreUpdate, (accumulate_player_input, accumulate_gamepad_input).after(InputSystem))
+		.add_systems(FixedUpdate, sample_player_input.in_set(SamplePlayerInput))
+		.add_systems(Update, update_cursor_grab)
 		;
 	}
 }
+
+fn accumulate_player_input(
+	mut mouse_inputs: EventReader<MouseMotion>,
+	keyboard_inputs: Res<Input<KeyCode>>,
+	input_map: Res<InputMap>,
+	cursor_grab: Res<CursorGrab>,
+	mut pending: ResMut<PendingPlayerInput>,
+) {
+	let axis = |positive: KeyCode, negative: KeyCode| {
+		keyboard_inputs.pressed(positive) as i32 as f32 - keyboard_inputs.pressed(negative) as i32 as f32
+	};
+
+	let mut mouse_delta = Vec2::ZERO;
+	for input in mouse_inputs.read() {
+		mouse_delta += input.delta;
+	}
+	// A free cursor is the user doing something else, it should neither turn the player nor count as circling
+	if !cursor_grab.grabbed {
+		mouse_delta = Vec2::ZERO;
+	}
+
+	let pending = pending.0.entry(InputSource::KeyboardMouse).or_default();
+	// Keys pressed while UI has the cursor are typed into it, they are not meant for the player
+	if cursor_grab.released_by_ui {
+		pending.accumulate(&PlayerInput {mouse_delta, ..Default::default()});
+		return;
+	}
+
+	pending.accumulate(&PlayerInput {
+		mouse_delta,
+		velocity_lock_pressed: keyboard_inputs.just_pressed(input_map.velocity_lock),
+		velocity_lock_held: keyboard_inputs.pressed(input_map.velocity_lock),
+		jump_pressed: keyboard_inputs.just_pressed(input_map.jump),
+		jump_held: keyboard_inputs.pressed(input_map.jump),
+		crouch_held: keyboard_inputs.pressed(input_map.crouch),
+		grapple_pressed: keyboard_inputs.just_pressed(input_map.grapple),
+		grapple_reel_in_held: keyboard_inputs.pressed(input_map.grapple_reel_in),
+		grapple_reel_out_held: keyboard_inputs.pressed(input_map.grapple_reel_out),
+		dash_pressed: keyboard_inputs.just_pressed(input_map.dash),
+		respawn_pressed: keyboard_inputs.just_pressed(input_map.respawn),
+		camera_mode_pressed: keyboard_inputs.just_pressed(input_map.camera_mode),
+		lock_on_pressed: keyboard_inputs.just_pressed(input_map.lock_on),
+		lock_on_cycle_pressed: keyboard_inputs.just_pressed(input_map.lock_on_cycle),
+		// Picked on the HUD, see `PendingPlayerInput::accumulate`
+		lock_on_select: None,
+		spectator_move: Vec2::new(
+			axis(input_map.spectator_right, input_map.spectator_left),
+			axis(input_map.spectator_forward, input_map.spectator_back),
+		),
+	});
+}
+
+/// Gathers what every connected gamepad did, tilting the right stick stands in for moving the mouse
+fn accumulate_gamepad_input(
+	gamepads: Res<Gamepads>,
+	buttons: Res<Input<GamepadButton>>,
+	axes: Res<Axis<GamepadAxis>>,
+	gamepad_map: Res<GamepadMap>,
+	input_settings: Res<InputSettings>,
+	time: Res<Time>,
+	mut pending: ResMut<PendingPlayerInput>,
+) {
+	for gamepad in gamepads.iter() {
+		let pressed = |button| buttons.pressed(GamepadButton::new(gamepad, button));
+		let just_pressed = |button| buttons.just_pressed(GamepadButton::new(gamepad, button));
+		let stick = |x, y| Vec2::new(
+			axes.get(GamepadAxis::new(gamepad, x)).unwrap_or(0.0),
+			axes.get(GamepadAxis::new(gamepad, y)).unwrap_or(0.0),
+		);
+		let look = stick(GamepadAxisType::RightStickX, GamepadAxisType::RightStickY);
+
+		pending.0.entry(InputSource::Gamepad(gamepad)).or_default().accumulate(&PlayerInput {
+			// Stick up looks up, the mouse moves down for that
+			mouse_delta: input_settings.gamepad_look_speed * time.delta_seconds() * Vec2::new(look.x, -look.y),
+			velocity_lock_pressed: just_pressed(gamepad_map.velocity_lock),
+			velocity_lock_held: pressed(gamepad_map.velocity_lock),
+			jump_pressed: just_pressed(gamepad_map.jump),
+			jump_held: pressed(gamepad_map.jump),
+			crouch_held: pressed(gamepad_map.crouch),
+			grapple_pressed: just_pressed(gamepad_map.grapple),
+			grapple_reel_in_held: pressed(gamepad_map.grapple_reel_in),
+			grapple_reel_out_held: pressed(gamepad_map.grapple_reel_out),
+			dash_pressed: just_pressed(gamepad_map.dash),
+			respawn_pressed: just_pressed(gamepad_map.respawn),
+			camera_mode_pressed: just_pressed(gamepad_map.camera_mode),
+			lock_on_pressed: just_pressed(gamepad_map.lock_on),
+			lock_on_cycle_pressed: just_pressed(gamepad_map.lock_on_cycle),
+			lock_on_select: None,
+			spectator_move: stick(GamepadAxisType::LeftStickX, GamepadAxisType::LeftStickY),
+		});
+	}
+}
+
+/// Hands the gathered input over to the current fixed tick, every local player gets what its own input source did
+fn sample_player_input(
+	mut pending: ResMut<PendingPlayerInput>,
+	mut local_players: Query<(&LocalInput, &mut PlayerInput)>,
+) {
+	for (local_input, mut player_input) in local_players.iter_mut() {
+		*player_input = pending.0.get(&local_input.source).copied().unwrap_or_default();
+	}
+	for input in pending.0.values_mut() {
+		*input = input.consumed();
+	}
+}
+
+fn update_cursor_grab(
+	mut windows: Query<&mut Window, With<PrimaryWindow>>,
+	game_state: Res<State<GameState>>,
+	mut cursor_grab: ResMut<CursorGrab>,
+) {
+	let Ok(mut window) = windows.get_single_mut() else {return;};
+
+	let should_grab = *game_state.get() == GameState::InLevel
+		&& !cursor_grab.released_by_ui
+		&& window.focused;
+
+	if should_grab == cursor_grab.grabbed {return;}
+	cursor_grab.grabbed = should_grab;
+
+	if should_grab {
+		window.cursor.grab_mode = CursorGrabMode::Locked;
+		window.cursor.visible = false;
+	} else {
+		window.cursor.grab_mode = CursorGrabMode::None;
+		window.cursor.visible = true;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::player::PlayerMovement;
+	use crate::testing;
+
+	/// Circles the mouse to accelerate, and jumps, dashes, locks and crouches along the way
+	fn recording() -> Vec<PlayerInput> {
+		(0..300).map(|tick| {
+			let angle = 0.3 * tick as f32;
+			PlayerInput {
+				mouse_delta: 40.0 * Vec2::new(f32::cos(angle), f32::sin(angle)),
+				jump_pressed: tick % 60 == 30,
+				jump_held: (30..40).contains(&(tick % 60)),
+				dash_pressed: tick == 100,
+				velocity_lock_pressed: tick == 150 || tick == 190,
+				crouch_held: (220..260).contains(&tick),
+				..Default::default()
+			}
+		}).collect()
+	}
+
+	/// Plays the input back on a fresh app, returns the state the player ends up in
+	fn replay(recording: &[PlayerInput]) -> (Transform, PlayerMovement) {
+		let mut app = testing::simulation_app();
+		testing::spawn_ground(&mut app);
+		let player = testing::enter_level(&mut app);
+		// The recording stands in for the keyboard and mouse
+		app.world.entity_mut(player).remove::<LocalInput>();
+		testing::place_player(&mut app, player, Vec3::new(0.0, 1.6, 0.0));
+
+		for input in recording {
+			*app.world.get_mut::<PlayerInput>(player).unwrap() = *input;
+			testing::step(&mut app);
+		}
+		(*app.world.get::<Transform>(player).unwrap(), *app.world.get::<PlayerMovement>(player).unwrap())
+	}
+
+	fn bits(values: &[f32]) -> Vec<u32> {
+		values.iter().map(|value| value.to_bits()).collect()
+	}
+
+	#[test]
+	fn same_input_gives_bit_identical_simulation() {
+		let recording = recording();
+		let (first_transform, first_movement) = replay(&recording);
+		let (second_transform, second_movement) = replay(&recording);
+
+		assert_eq!(bits(&first_transform.compute_matrix().to_cols_array()), bits(&second_transform.compute_matrix().to_cols_array()));
+		assert_eq!(bits(&first_transform.rotation.to_array()), bits(&second_transform.rotation.to_array()));
+		assert_eq!(bits(&first_movement.desired_velocity.to_array()), bits(&second_movement.desired_velocity.to_array()));
+		assert_eq!(first_movement.time_accelerating.to_bits(), second_movement.time_accelerating.to_bits());
+		assert_eq!(first_movement.state, second_movement.state);
+		// The recording has to actually have gone somewhere for this to mean anything
+		assert!(first_transform.translation.distance(Vec3::new(0.0, 1.6, 0.0)) > 1.0);
+	}
+}
@@ -0,0 +1,229 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::checkpoint::{PlayerRespawned, update_checkpoints};
+use crate::input::{PlayerInput, SamplePlayerInput};
+use crate::meme::{ContainerActivity, MemeContainer};
+use crate::player::{Player, PlayerCamera, PlayerImpact, find_eye_and_view, read_result_system};
+use crate::tuning::{IntegrityTuning, MovementTuning};
+
+pub struct IntegrityPlugin;
+
+/// How intact a player still is, they are destroyed once it runs out
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct PlayerIntegrity {
+	/// What is left of the integrity, between zero and `max_integrity`
+	pub integrity: f32,
+	/// How long ago the player last took damage, regeneration waits for this [s]
+	pub time_since_damage: f32,
+	/// Time left until a destroyed player respawns, `None` while not destroyed [s]
+	pub respawn_timer: Option<f32>,
+}
+
+impl PlayerIntegrity {
+	pub fn new(tuning: &IntegrityTuning) -> Self {
+		Self {
+			integrity: tuning.max_integrity,
+			time_since_damage: 0.0,
+			respawn_timer: None,
+		}
+	}
+
+	pub fn is_destroyed(&self) -> bool {
+		self.respawn_timer.is_some()
+	}
+
+	/// Whether the player has been destroyed for long enough to be respawned
+	pub fn ready_to_respawn(&self) -> bool {
+		self.respawn_timer.is_some_and(|time_left| time_left <= 0.0)
+	}
+}
+
+/// Marks a sensor collider that damages players inside of it
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct Hazard {
+	/// Damage dealt to a player for every second spent inside [1/s]
+	pub damage_per_second: f32,
+}
+
+/// What hurt a player
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DamageSource {
+	/// Slamming into a surface too fast
+	Impact { surface: Entity },
+	/// Being inside of a `Hazard`
+	Hazard(Entity),
+	/// Looking at an active infohazard
+	Memetic(Entity),
+}
+
+/// Every bit of damage a player takes goes through this event, whatever its source
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub struct PlayerDamaged {
+	pub player: Entity,
+	pub amount: f32,
+	pub source: DamageSource,
+}
+
+/// Sent when the integrity of a player runs out
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PlayerDestroyed {
+	pub player: Entity,
+}
+
+impl Plugin for IntegrityPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_event::<PlayerDamaged>()
+			.add_event::<PlayerDestroyed>()
+			.add_systems(FixedUpdate, silence_destroyed_players.after(SamplePlayerInput).before(update_checkpoints))
+			.add_systems(FixedUpdate, restore_integrity_on_respawn.after(update_checkpoints))
+			.add_systems(FixedUpdate, (
+				damage_from_impacts,
+				damage_from_hazards,
+				damage_from_memetics,
+				apply_damage,
+			).chain().after(read_result_system))
+		;
+	}
+}
+
+/// Destroyed players can't do anything but ask to respawn early
+fn silence_destroyed_players(
+	mut players: Query<(&PlayerIntegrity, &mut PlayerInput), With<Player>>,
+) {
+	for (integrity, mut player_input) in players.iter_mut() {
+		if !integrity.is_destroyed() {continue;}
+		*player_input = PlayerInput {
+			respawn_pressed: player_input.respawn_pressed,
+			..Default::default()
+		};
+	}
+}
+
+/// Puts destroyed players back together once they respawn, respawning any other way leaves the damage taken as it is
+fn restore_integrity_on_respawn(
+	mut respawn_events: EventReader<PlayerRespawned>,
+	mut players: Query<&mut PlayerIntegrity>,
+	tuning: Res<MovementTuning>,
+) {
+	for respawn in respawn_events.read() {
+		let Ok(mut integrity) = players.get_mut(respawn.player) else {continue;};
+		if !integrity.is_destroyed() {continue;}
+		*integrity = PlayerIntegrity::new(&tuning.integrity);
+	}
+}
+
+fn damage_from_impacts(
+	mut impact_events: EventReader<PlayerImpact>,
+	tuning: Res<MovementTuning>,
+	mut damage_events: EventWriter<PlayerDamaged>,
+) {
+	for impact in impact_events.read() {
+		let excess_speed = impact.impact_speed - tuning.integrity.impact_damage_min_speed;
+		if excess_speed <= 0.0 {continue;}
+		damage_events.send(PlayerDamaged {
+			player: impact.player,
+			amount: tuning.integrity.impact_damage_per_speed * excess_speed,
+			source: DamageSource::Impact { surface: impact.surface },
+		});
+	}
+}
+
+fn damage_from_hazards(
+	players: Query<(Entity, &Transform, &Collider), With<Player>>,
+	hazards: Query<&Hazard>,
+	rapier_context: Res<RapierContext>,
+	time: Res<Time>,
+	mut damage_events: EventWriter<PlayerDamaged>,
+) {
+	let dt = time.delta_seconds();
+	for (player, transform, collider) in players.iter() {
+		rapier_context.intersections_with_shape(
+			transform.translation,
+			transform.rotation,
+			collider,
+			QueryFilter::default().exclude_solids().exclude_collider(player),
+			|sensor| {
+				if let Ok(hazard) = hazards.get(sensor) {
+					damage_events.send(PlayerDamaged {
+						player,
+						amount: hazard.damage_per_second * dt,
+						source: DamageSource::Hazard(sensor),
+					});
+				}
+				true
+			},
+		);
+	}
+}
+
+/// Active infohazards damage the players looking at them, as long as nothing is in the way
+fn damage_from_memetics(
+	players: Query<(Entity, &Transform), With<Player>>,
+	cameras: Query<(&Transform, &PlayerCamera, &Parent), Without<Player>>,
+	containers: Query<(Entity, &Transform, &MemeContainer), Without<Player>>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+	mut damage_events: EventWriter<PlayerDamaged>,
+) {
+	let dt = time.delta_seconds();
+	let max_angle = tuning.integrity.memetic_exposure_angle_degrees.to_radians();
+	for (player, player_transform) in players.iter() {
+		let Some((eye, view_direction)) = find_eye_and_view(player, player_transform, cameras.iter()) else {continue;};
+
+		for (container, container_transform, meme) in containers.iter() {
+			if !meme.contains_infohazard || meme.container_activity != ContainerActivity::Active {continue;}
+			let to_container = container_transform.translation - eye;
+			let distance = to_container.length();
+			if distance > tuning.integrity.memetic_exposure_range || distance < 0.001 {continue;}
+			if to_container.angle_between(view_direction) > max_angle {continue;}
+			let in_sight = rapier_context.cast_ray(
+				eye,
+				to_container / distance,
+				distance,
+				true,
+				QueryFilter::default().exclude_collider(player).exclude_sensors(),
+			).is_none_or(|(hit, _)| hit == container);
+			if !in_sight {continue;}
+
+			damage_events.send(PlayerDamaged {
+				player,
+				amount: tuning.integrity.memetic_damage_per_second * dt,
+				source: DamageSource::Memetic(container),
+			});
+		}
+	}
+}
+
+/// Takes damage off of players' integrity, regenerates it and counts down to the respawn of destroyed players
+fn apply_damage(
+	mut players: Query<&mut PlayerIntegrity>,
+	mut damage_events: EventReader<PlayerDamaged>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+	mut destroyed_events: EventWriter<PlayerDestroyed>,
+) {
+	let dt = time.delta_seconds();
+	for mut integrity in players.iter_mut() {
+		integrity.time_since_damage += dt;
+		if let Some(time_left) = integrity.respawn_timer.as_mut() {
+			*time_left = f32::max(*time_left - dt, 0.0);
+		} else if integrity.time_since_damage >= tuning.integrity.integrity_regen_delay {
+			integrity.integrity = f32::min(integrity.integrity + tuning.integrity.integrity_regen * dt, tuning.integrity.max_integrity);
+		}
+	}
+
+	for damage in damage_events.read() {
+		let Ok(mut integrity) = players.get_mut(damage.player) else {continue;};
+		if integrity.is_destroyed() || damage.amount <= 0.0 {continue;}
+		integrity.integrity -= damage.amount;
+		integrity.time_since_damage = 0.0;
+		if integrity.integrity <= 0.0 {
+			integrity.integrity = 0.0;
+			integrity.respawn_timer = Some(tuning.integrity.respawn_delay);
+			destroyed_events.send(PlayerDestroyed { player: damage.player });
+		}
+	}
+}
@@ -1,34 +1,96 @@
+// Bevy systems take everything they work with as arguments, and queries spell out what they fetch
+#![allow(clippy::type_complexity, clippy::too_many_arguments)]
+
 mod bevy_simple_text_input;
+mod camera_effects;
+mod camera_rig;
+mod checkpoint;
+mod grapple;
+mod gravity;
+mod platform;
 mod player;
+mod props;
 mod state;
+mod targeting;
 #[cfg(debug_assertions)]
 mod debug;
 mod input;
+mod integrity;
+mod marble;
 mod meme;
+mod tuning;
 mod ui;
+#[cfg(test)]
+mod testing;
 
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::*;
 
 fn main() {
 	let mut app: App = App::new();
-	app.add_state::<state::GameState>();
 	app.add_plugins(DefaultPlugins);
-	app.insert_resource(RapierConfiguration {
-		gravity: Vec3::new(0.0, -9.81, 0.0),
-		..Default::default()
-	});
-	app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default());
+	add_simulation(&mut app);
 	#[cfg(debug_assertions)]
 	{app.add_plugins(debug::DebugPlugin);}
 	app.add_systems(Startup, (test_setup, setup_test_room));
 	app.add_plugins((
         bevy_simple_text_input::TextInputPlugin,
 		ui::UIPlugin,
+	));
+	app.run();
+}
+
+/// Adds physics and everything that plays out in a level, but nothing that only draws it or is UI,
+/// tests run the simulation on its own (see `testing`)
+fn add_simulation(app: &mut App) {
+	app.add_state::<state::GameState>();
+	app.insert_resource(RapierConfiguration {
+		gravity: Vec3::new(0.0, -9.81, 0.0),
+		// Physics steps once per fixed tick, together with the player simulation
+		timestep_mode: TimestepMode::Fixed {
+			dt: Time::<Fixed>::default().timestep().as_secs_f32(),
+			substeps: 1,
+		},
+		..Default::default()
+	});
+	app.add_plugins(RapierPhysicsPlugin::<NoUserData>::default().with_default_system_setup(false));
+	add_physics_to_fixed_update(app);
+	app.add_plugins((
 		input::InputPlugin,
+		tuning::TuningPlugin,
 		player::PlayerPlugin,
+		grapple::GrapplePlugin,
+		gravity::GravityPlugin,
+		checkpoint::CheckpointPlugin,
+		camera_effects::CameraEffectsPlugin,
+		camera_rig::CameraRigPlugin,
+		marble::MarblePlugin,
+		platform::PlatformPlugin,
+		props::PropsPlugin,
+		integrity::IntegrityPlugin,
+		targeting::TargetingPlugin,
 	));
-	app.run();
+}
+
+/// Sets up Rapier's systems to run in `FixedUpdate` instead of once per rendered frame,
+/// so physics advances in lockstep with the player simulation
+fn add_physics_to_fixed_update(app: &mut App) {
+	app.configure_sets(
+		FixedUpdate,
+		(
+			PhysicsSet::SyncBackend,
+			PhysicsSet::StepSimulation,
+			PhysicsSet::Writeback,
+		).chain(),
+	);
+	app.add_systems(
+		FixedUpdate,
+		(
+			RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::SyncBackend).in_set(PhysicsSet::SyncBackend),
+			RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::StepSimulation).in_set(PhysicsSet::StepSimulation),
+			RapierPhysicsPlugin::<NoUserData>::get_systems(PhysicsSet::Writeback).in_set(PhysicsSet::Writeback),
+		),
+	);
 }
 
 fn test_setup(
@@ -40,13 +102,15 @@ fn test_setup(
 	//	transform: Transform::from_translation(Vec3::new(-5.0, 2.0, 1.0)).looking_at(Vec3::ZERO, Vec3::Y),
 	//	..Default::default()
 	//});
+	// A prop to run into, gets pushed harder the faster the player goes and smashed away above `prop_smash_speed`
 	commands.spawn((
 		PbrBundle {
 			mesh: cube,
+			transform: Transform::from_xyz(0.0, 0.5, -8.0),
 			..Default::default()
 		},
 		(RigidBody::Dynamic, Velocity::zero(), GravityScale(1.0), Sleeping::disabled(),
-		Collider::cuboid(0.5, 0.5, 0.5), ColliderMassProperties::Mass(1.0))
+		Collider::cuboid(0.5, 0.5, 0.5), ColliderMassProperties::Mass(5.0))
 	));
 }
 
@@ -77,5 +141,36 @@ fn setup_test_room(
 			contains_infohazard: true
 		},
 	));
+	commands.spawn((
+		TransformBundle::from_transform(Transform::from_xyz(0.0, 2.0, 0.0)),
+		(Sensor, Collider::cuboid(2.0, 2.0, 2.0)),
+		checkpoint::Checkpoint,
+	));
+	let platform = meshes.add(bevy::render::mesh::shape::Box::new(6.0, 0.5, 6.0).into());
+	commands.spawn((
+		PbrBundle {
+			mesh: platform,
+			transform: Transform::from_xyz(10.0, 1.0, 0.0),
+			..Default::default()
+		},
+		Collider::cuboid(3.0, 0.25, 3.0),
+		platform::MovingPlatform::new(vec![Vec3::new(10.0, 1.0, 0.0), Vec3::new(10.0, 1.0, 20.0), Vec3::new(10.0, 6.0, 20.0)], 3.0)
+			.with_spin(0.3 * Vec3::Y),
+	));
+	// A wall that can be walked on, gravity pulls towards it inside of the zone in front of it
+	let wall = meshes.add(bevy::render::mesh::shape::Box::new(0.5, 20.0, 20.0).into());
+	commands.spawn((
+		PbrBundle {
+			mesh: wall,
+			transform: Transform::from_xyz(-20.0, 10.0, 0.0),
+			..Default::default()
+		},
+		Collider::cuboid(0.25, 10.0, 10.0),
+	));
+	commands.spawn((
+		TransformBundle::from_transform(Transform::from_xyz(-16.0, 10.0, 0.0)),
+		(Sensor, Collider::cuboid(3.75, 10.0, 10.0)),
+		gravity::GravityZone { direction: Vec3::NEG_X },
+	));
 	game_state.0 = Some(state::GameState::InLevel);
 }
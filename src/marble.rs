@@ -0,0 +1,181 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::gravity::body_gravity;
+use crate::player::{Player, PlayerImpact, PlayerMovement, is_prop, player_dash, player_kinematics, read_result_system, restore_simulated_transform, rotate_player};
+use crate::tuning::MovementTuning;
+
+/// Radius of the marble
+pub const MARBLE_RADIUS: f32 = 0.75;
+/// Mass of the marble [kg]
+const MARBLE_MASS: f32 = 20.0;
+/// Moment of inertia of the marble, that of a solid ball [kg*m^2]
+const MARBLE_INERTIA: f32 = 0.4 * MARBLE_MASS * MARBLE_RADIUS * MARBLE_RADIUS;
+/// How far below the bottom of the marble ground still counts as touched [m]
+pub const MARBLE_CONTACT_TOLERANCE: f32 = 0.05;
+
+pub struct MarblePlugin;
+
+/// Marks a player that is a dynamic ball rolled around by torque, instead of a capsule moved by a `KinematicCharacterController`
+///
+/// The physics engine only sees the rolling orientation while it steps, the rest of the time the transform holds where
+/// the player is facing, so neither the camera nor the movement code roll along
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
+pub struct Marble {
+	/// Where the player faces, parked here while the physics engine steps
+	facing: Quat,
+	/// Orientation of the ball in world space, parked here the rest of the time
+	roll: Quat,
+}
+
+/// Visible ball of a marble player, turned to show how the marble rolled
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub struct MarbleMesh;
+
+/// Surfaces a marble started touching during the last physics step, with their normals pointing towards the marble
+///
+/// Takes the place of the collisions a `KinematicCharacterControllerOutput` lists for capsule players
+#[derive(Component, Clone, PartialEq, Debug, Default)]
+pub struct MarbleContacts(pub Vec<(Entity, Vec3)>);
+
+/// Everything `marble_body` adds, for taking it off again
+pub type MarbleBody = (
+	RigidBody, Collider, ColliderMassProperties, Restitution, Friction, GravityScale, Velocity,
+	ExternalForce, ExternalImpulse, Ccd, ActiveEvents, Marble, MarbleContacts,
+);
+
+impl Plugin for MarblePlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(FixedUpdate, (
+				sync_marble.after(restore_simulated_transform).before(rotate_player),
+				drive_marble.after(player_dash).before(player_kinematics),
+				hand_roll_to_physics.after(player_kinematics).after(drive_marble).before(PhysicsSet::SyncBackend),
+				(
+					take_roll_from_physics,
+					read_marble_contacts,
+				).chain().after(PhysicsSet::Writeback).before(read_result_system),
+			))
+		;
+	}
+}
+
+/// Everything the physics engine needs to simulate a marble player
+pub fn marble_body(tuning: &MovementTuning) -> impl Bundle {
+	(
+		RigidBody::Dynamic,
+		Collider::ball(MARBLE_RADIUS),
+		ColliderMassProperties::Mass(MARBLE_MASS),
+		Restitution::coefficient(tuning.marble_restitution),
+		Friction::coefficient(tuning.marble_friction),
+		// The physics engine applies gravity, `drive_marble` only bends it where zones and surfaces call for it
+		GravityScale(1.0),
+		Velocity::default(),
+		ExternalForce::default(),
+		Ccd::enabled(),
+		ActiveEvents::COLLISION_EVENTS,
+		Marble::default(),
+		MarbleContacts::default(),
+	)
+}
+
+/// Takes the velocity of marbles over from the physics engine, the movement code then works off of it
+fn sync_marble(
+	mut players: Query<(&Velocity, &mut PlayerMovement), (With<Player>, With<Marble>)>,
+) {
+	for (velocity, mut player) in players.iter_mut() {
+		player.desired_velocity = velocity.linvel - player.ground_velocity;
+	}
+}
+
+/// Spins marbles towards rolling at the velocity the movement code wants them to have, friction with the ground does the rest
+///
+/// Only what rolling can't do is pushed directly: leaving the ground, and anything at all while in the air
+pub(crate) fn drive_marble(
+	mut players: Query<(&Velocity, Option<&GravityScale>, &mut ExternalForce, &mut PlayerMovement), (With<Player>, With<Marble>)>,
+	tuning: Res<MovementTuning>,
+	rapier_config: Res<RapierConfiguration>,
+	time: Res<Time>,
+) {
+	let dt = time.delta_seconds();
+	if dt <= 0.0 {return;}
+	for (velocity, gravity_scale, mut force, mut player) in players.iter_mut() {
+		player.update_jump(&tuning, dt);
+
+		let target_velocity = player.desired_velocity + player.ground_velocity;
+		let velocity_change = target_velocity - velocity.linvel;
+		let pushed_change = if player.ground_contact {player.vertical(velocity_change) * player.up} else {velocity_change};
+		force.force = MARBLE_MASS * pushed_change / dt;
+		force.force += MARBLE_MASS * (-player.gravity * player.up - body_gravity(&rapier_config, gravity_scale));
+
+		force.torque = Vec3::ZERO;
+		if !player.ground_contact {continue;}
+		// Rolling without slipping turns the ball around the axis perpendicular to where it's going
+		let target_spin = player.up.cross(player.desired_velocity) / MARBLE_RADIUS;
+		let spin = player.horizontal(velocity.angvel);
+		force.torque = MARBLE_INERTIA * (target_spin - spin) / dt;
+		if spin.length_squared() > 0.0001 {
+			force.torque -= tuning.marble_rolling_resistance * MARBLE_MASS * player.gravity * MARBLE_RADIUS * spin.normalize();
+		}
+	}
+}
+
+/// Gives the physics engine the rolling orientation of marbles to step with
+fn hand_roll_to_physics(
+	mut players: Query<(&mut Transform, &mut Marble), With<Player>>,
+) {
+	for (mut transform, mut marble) in players.iter_mut() {
+		marble.facing = transform.rotation;
+		transform.rotation = marble.roll;
+	}
+}
+
+/// Takes the rolling orientation of marbles back from the physics engine, and turns their visible ball to match
+fn take_roll_from_physics(
+	mut players: Query<(&mut Transform, &mut Marble), With<Player>>,
+	mut meshes: Query<(&mut Transform, &Parent), (With<MarbleMesh>, Without<Player>)>,
+) {
+	for (mut transform, mut marble) in players.iter_mut() {
+		marble.roll = transform.rotation;
+		transform.rotation = marble.facing;
+	}
+	for (mut mesh_transform, parent) in meshes.iter_mut() {
+		let Ok((_, marble)) = players.get(parent.get()) else {continue;};
+		mesh_transform.rotation = marble.facing.inverse() * marble.roll;
+	}
+}
+
+/// Lists what marbles ran into during the last physics step,
+/// and reports hard hits against what they can't stand on, the way `read_result_system` does for capsules
+pub(crate) fn read_marble_contacts(
+	mut players: Query<(&PlayerMovement, &mut MarbleContacts), With<Player>>,
+	bodies: Query<&RigidBody>,
+	mut collision_events: EventReader<CollisionEvent>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+	mut impact_events: EventWriter<PlayerImpact>,
+) {
+	for (_, mut contacts) in players.iter_mut() {
+		contacts.0.clear();
+	}
+	let max_slope = tuning.max_walkable_slope_degrees.to_radians();
+	for event in collision_events.read() {
+		let CollisionEvent::Started(collider1, collider2, _) = *event else {continue;};
+		let (player, surface) = if players.contains(collider1) {(collider1, collider2)} else {(collider2, collider1)};
+		let Ok((movement, mut contacts)) = players.get_mut(player) else {continue;};
+		// Sensors only intersect, they have no contact pair
+		let Some(pair) = rapier_context.contact_pair(player, surface) else {continue;};
+		let Some(manifold) = pair.manifolds().find(|manifold| manifold.num_points() > 0) else {continue;};
+		// The manifold normal points away from the first collider of the pair
+		let normal = if pair.collider1() == player {-manifold.normal()} else {manifold.normal()};
+		contacts.0.push((surface, normal));
+
+		if is_prop(surface, &bodies, &rapier_context) {continue;}
+		if normal.angle_between(movement.up) <= max_slope {continue;}
+		// What the marble moved at going into the step, the physics engine already bounced it off
+		let impact_speed = -(movement.desired_velocity + movement.ground_velocity).dot(normal);
+		if impact_speed >= tuning.impact_event_min_speed {
+			impact_events.send(PlayerImpact { player, surface, normal, impact_speed });
+		}
+	}
+}
@@ -0,0 +1,136 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::input::SamplePlayerInput;
+use crate::player::{Player, PlayerMovement, detect_ground, update_movement_state};
+
+pub struct PlatformPlugin;
+
+/// A kinematic body that travels back and forth along a list of waypoints, optionally spinning as it goes
+///
+/// Gets its `RigidBody` and `Velocity` added automatically, only needs a `Collider` and a `Transform` at the first waypoint
+#[derive(Component, Clone, PartialEq, Debug, Default)]
+pub struct MovingPlatform {
+	/// Points the platform moves between, in world space
+	pub waypoints: Vec<Vec3>,
+	/// How quickly the platform travels between waypoints [m/s]
+	pub speed: f32,
+	/// Angular velocity the platform spins with the whole time [radian/s]
+	pub spin: Vec3,
+	/// Index of the waypoint the platform is heading towards
+	next_waypoint: usize,
+	/// Whether the waypoints are being walked in reverse
+	returning: bool,
+}
+
+impl MovingPlatform {
+	pub fn new(waypoints: Vec<Vec3>, speed: f32) -> Self {
+		Self {
+			waypoints,
+			speed,
+			..Default::default()
+		}
+	}
+
+	pub fn with_spin(mut self, spin: Vec3) -> Self {
+		self.spin = spin;
+		self
+	}
+
+	/// Moves on to the following waypoint, turning around at either end
+	fn advance(&mut self) {
+		let count = self.waypoints.len();
+		if count < 2 {return;}
+		if self.next_waypoint == count - 1 {
+			self.returning = true;
+		} else if self.next_waypoint == 0 {
+			self.returning = false;
+		}
+		if self.returning {
+			self.next_waypoint -= 1;
+		} else {
+			self.next_waypoint += 1;
+		}
+	}
+}
+
+impl Plugin for PlatformPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_systems(FixedUpdate, (
+				add_body_to_platforms,
+				move_platforms,
+			).chain().before(PhysicsSet::SyncBackend))
+			.add_systems(FixedUpdate, follow_ground_body
+				.after(SamplePlayerInput)
+				.after(detect_ground)
+				.before(update_movement_state))
+		;
+	}
+}
+
+fn add_body_to_platforms(
+	mut commands: Commands,
+	platforms: Query<Entity, (With<MovingPlatform>, Without<RigidBody>)>,
+) {
+	for platform in platforms.iter() {
+		commands.entity(platform).insert((RigidBody::KinematicVelocityBased, Velocity::zero()));
+	}
+}
+
+fn move_platforms(
+	mut platforms: Query<(&Transform, &mut MovingPlatform, &mut Velocity)>,
+	time: Res<Time>,
+) {
+	let dt = time.delta_seconds();
+	if dt <= 0.0 {return;}
+	for (transform, mut platform, mut velocity) in platforms.iter_mut() {
+		velocity.angvel = platform.spin;
+		let Some(&target) = platform.waypoints.get(platform.next_waypoint) else {
+			velocity.linvel = Vec3::ZERO;
+			continue;
+		};
+		let to_target = target - transform.translation;
+		let step = platform.speed * dt;
+		if to_target.length() <= step {
+			platform.advance();
+		}
+		// Never overshoot, arrive exactly on the waypoint and turn towards the next one the tick after
+		velocity.linvel = to_target.clamp_length_max(step) / dt;
+	}
+}
+
+/// Carries players along with whatever body they are standing on
+///
+/// `desired_velocity` is relative to the ground body, when the player steps off of it (or jumps)
+/// the velocity of the body is handed over, so the player keeps moving with it
+pub(crate) fn follow_ground_body(
+	mut players: Query<(&mut Transform, &mut PlayerMovement), With<Player>>,
+	bodies: Query<(&Transform, &Velocity), Without<Player>>,
+	rapier_context: Res<RapierContext>,
+	time: Res<Time>,
+) {
+	let dt = time.delta_seconds();
+	for (mut transform, mut player) in players.iter_mut() {
+		let body = player.ground_collider
+			.filter(|_| player.ground_contact)
+			.map(|collider| rapier_context.collider_parent(collider).unwrap_or(collider));
+		let (ground_velocity, angular_velocity) = body
+			.and_then(|body| bodies.get(body).ok())
+			.map_or((Vec3::ZERO, Vec3::ZERO), |(body_transform, velocity)| (
+				velocity.linvel + velocity.angvel.cross(transform.translation - body_transform.translation),
+				velocity.angvel,
+			));
+
+		if body != player.ground_body {
+			// Keep the velocity we had in the world while switching what it is relative to
+			let previous_velocity = player.ground_velocity;
+			player.desired_velocity += previous_velocity - ground_velocity;
+			player.ground_body = body;
+		}
+		player.ground_velocity = ground_velocity;
+		// Spinning platforms turn the player with them
+		let up = player.up;
+		transform.rotate_axis(up, angular_velocity.dot(up) * dt);
+	}
+}
@@ -1,30 +1,306 @@
 use std::f32::consts::PI;
 
-use bevy::{prelude::*, input::mouse::MouseMotion};
+use bevy::{prelude::*, transform::TransformSystem};
 use bevy_rapier3d::prelude::*;
 
-use crate::input::{InputSettings, InputMap};
+use crate::camera_rig::{CameraMode, CameraRig};
+use crate::checkpoint::RespawnPoint;
+use crate::grapple::Grapple;
+use crate::input::{GamepadMap, InputSettings, InputSource, LocalInput, PlayerInput, SamplePlayerInput};
+use crate::integrity::PlayerIntegrity;
+use crate::marble::{Marble, MarbleBody, MarbleMesh, MARBLE_CONTACT_TOLERANCE, MARBLE_RADIUS, marble_body};
+use crate::targeting::LockOn;
+use crate::tuning::{MovementTuning, MomentumLockSnap, PlayerControllerKind};
+
+/// Half of the length of the cylindrical part of the player's capsule
+const PLAYER_CAPSULE_HALF_HEIGHT: f32 = 1.0;
+/// Radius of the player's capsule
+const PLAYER_CAPSULE_RADIUS: f32 = 0.5;
+/// Speed away from the ground above which we are leaving it, rather than moving along it [m/s]
+const GROUND_SEPARATION_SPEED: f32 = 0.1;
 
 #[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
 pub struct Player;
 
+/// What the player is currently doing, decides which movement rules apply
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum MovementState {
+	/// Standing or running on walkable ground
+	Grounded,
+	/// In the air, or on ground too steep to stand on
+	#[default]
+	Airborne,
+	/// The velocity is locked from being changed by inputs, only ends when the lock is released
+	Locked,
+	/// Crouching on walkable ground fast enough to slide instead of walking
+	Sliding,
+	/// Running along a wall with reduced gravity
+	WallRunning,
+}
+
+/// Sent when a player slams into a surface it can't stand on
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub struct PlayerImpact {
+	pub player: Entity,
+	/// Entity that was hit
+	pub surface: Entity,
+	/// Normal of the surface that was hit, pointing towards the player
+	pub normal: Vec3,
+	/// Speed the player was moving into the surface at [m/s]
+	pub impact_speed: f32,
+}
+
+/// Sent whenever the `MovementState` of a player changes
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MovementStateChanged {
+	pub player: Entity,
+	pub from: MovementState,
+	pub to: MovementState,
+}
+
 /// Contains all game-logic information (input and kinematics) about player movement
 /// More relevant data can be found in an attached `Transform` and `KinematicCharacterController`
-#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
 pub struct PlayerMovement {
 	/// How long we've been accelerating, determines max speed among other things
 	pub time_accelerating: f32,
-	/// Velocity vector the player is trying to move at
+	/// Velocity vector the player is trying to move at, relative to the ground body we are standing on
 	pub desired_velocity: Vec3,
-	/// Whether the physics engine said we are touching ground
-	pub grounded: bool,
-	/// Whether the velocity vector should be locked from being changed by inputs
-	pub locked_velocity: bool,
-	/// Whether a velocity lock just started
-	pub just_locked_velocity: bool,
+	/// What we are currently doing, only to be changed through `transition`
+	pub state: MovementState,
+	/// Whether the physics engine said we are touching ground, regardless of whether it is walkable
+	pub ground_contact: bool,
+	/// How much of the momentum lock time budget has been spent
+	pub lock_time_used: f32,
+	/// How long ago we last touched ground, zero while grounded
+	pub time_since_grounded: f32,
+	/// How much longer a buffered jump press stays valid, zero if there is none
+	pub jump_buffer: f32,
+	/// Whether the jump key is being held
+	pub jump_held: bool,
+	/// Whether we are in the rising part of a jump that can still be cut short by releasing the jump key
+	pub jumping: bool,
+	/// Normal of the wall the physics engine said we are touching, if any
+	pub wall_normal: Option<Vec3>,
+	/// How long we've been wall running since last touching ground
+	pub time_wall_running: f32,
+	/// Normal of the ground directly below us, if there is any close enough
+	pub ground_normal: Option<Vec3>,
+	/// Collider of the ground directly below us, if there is any close enough
+	pub ground_collider: Option<Entity>,
+	/// Rigid body we are standing on, our velocity is relative to it
+	pub ground_body: Option<Entity>,
+	/// Velocity of the ground body at our position, zero while not standing on one
+	pub ground_velocity: Vec3,
+	/// Whether the crouch key is being held
+	pub crouch_held: bool,
+	/// Whether our collider is currently shrunk down
+	pub crouching: bool,
+	/// Whether the player is circling the mouse to accelerate
+	pub accelerating: bool,
+	/// Direction opposite to the gravity we are subject to, everything vertical is measured along it
+	pub up: Vec3,
+	/// Strength of the gravity pulling us along `-up`, taken from the physics configuration every tick [m/s^2]
+	pub gravity: f32,
+}
+
+impl Default for PlayerMovement {
+	fn default() -> Self {
+		Self {
+			time_accelerating: 0.0,
+			desired_velocity: Vec3::ZERO,
+			state: MovementState::default(),
+			ground_contact: false,
+			lock_time_used: 0.0,
+			time_since_grounded: 0.0,
+			jump_buffer: 0.0,
+			jump_held: false,
+			jumping: false,
+			wall_normal: None,
+			time_wall_running: 0.0,
+			ground_normal: None,
+			ground_collider: None,
+			ground_body: None,
+			ground_velocity: Vec3::ZERO,
+			crouch_held: false,
+			crouching: false,
+			accelerating: false,
+			up: Vec3::Y,
+			gravity: 0.0,
+		}
+	}
+}
+
+/// Energy and cooldown of the player's dash
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
+pub struct PlayerDash {
+	/// Energy available for dashing, regenerates while accelerating
+	pub energy: f32,
+	/// How much longer until the next dash is allowed
+	pub cooldown: f32,
+}
+
+impl PlayerMovement {
+	/// Half of the length of the cylindrical part of our capsule, depends on whether we are crouching
+	pub fn capsule_half_height(&self, tuning: &MovementTuning) -> f32 {
+		if self.crouching {tuning.crouch_half_height} else {PLAYER_CAPSULE_HALF_HEIGHT}
+	}
+
+	/// Part of a vector going along our up direction
+	pub fn vertical(&self, vector: Vec3) -> f32 {
+		vector.dot(self.up)
+	}
+
+	/// Part of a vector perpendicular to our up direction
+	pub fn horizontal(&self, vector: Vec3) -> Vec3 {
+		vector - vector.dot(self.up) * self.up
+	}
+
+	/// Replaces the vertical part of the velocity, leaving the horizontal part alone
+	pub fn set_vertical_speed(&mut self, speed: f32) {
+		self.desired_velocity += (speed - self.vertical(self.desired_velocity)) * self.up;
+	}
+
+	/// Keeps the velocity flush with the ground and lets gravity pull us downhill (and slow us down uphill)
+	pub fn follow_slope(&mut self, ground_normal: Vec3, tuning: &MovementTuning, dt: f32) {
+		self.desired_velocity -= self.desired_velocity.dot(ground_normal) * ground_normal;
+		let gravity = -self.gravity * self.up;
+		let downhill_gravity = gravity - gravity.dot(ground_normal) * ground_normal;
+		self.desired_velocity += tuning.slope_gravity_factor * dt * downhill_gravity;
+	}
+
+	/// Switches to another state, returns the event describing the change if there was one
+	pub fn transition(&mut self, player: Entity, to: MovementState) -> Option<MovementStateChanged> {
+		if self.state == to {return None;}
+		let from = self.state;
+		self.state = to;
+		Some(MovementStateChanged { player, from, to })
+	}
+
+	/// Whether we are touching ground that isn't too steep to stand on
+	pub fn on_walkable_ground(&self, tuning: &MovementTuning) -> bool {
+		let max_slope = tuning.max_walkable_slope_degrees.to_radians();
+		self.ground_contact && !self.ground_normal.is_some_and(|normal| normal.angle_between(self.up) > max_slope)
+	}
+
+	/// Whether our wall contact and speed allow running along a wall
+	pub fn can_wall_run(&self, tuning: &MovementTuning) -> bool {
+		let Some(wall_normal) = self.wall_normal else {return false;};
+		// Only the part of our velocity going along the wall keeps us on it
+		let along_wall = self.desired_velocity - self.desired_velocity.dot(wall_normal) * wall_normal;
+		let horizontal_speed = self.horizontal(along_wall).length();
+		self.time_wall_running < tuning.wall_run_max_time && horizontal_speed >= tuning.wall_run_min_speed
+	}
+
+	/// The state our contacts and speed call for while no momentum lock is held
+	pub fn free_state(&self, tuning: &MovementTuning) -> MovementState {
+		if self.on_walkable_ground(tuning) {
+			let horizontal_speed = self.horizontal(self.desired_velocity).length();
+			if self.crouching && horizontal_speed >= tuning.slide_min_speed {
+				MovementState::Sliding
+			} else {
+				MovementState::Grounded
+			}
+		} else if self.can_wall_run(tuning) {
+			MovementState::WallRunning
+		} else {
+			MovementState::Airborne
+		}
+	}
+
+	/// Slides along or bounces off of a surface we hit, returns the speed we hit it at, if we were moving into it at all
+	pub fn collide(&mut self, normal: Vec3, tuning: &MovementTuning) -> Option<f32> {
+		let normal_speed = self.desired_velocity.dot(normal);
+		if normal_speed >= 0.0 {return None;}
+		let impact_speed = -normal_speed;
+
+		if impact_speed >= tuning.wall_bounce_min_speed {
+			self.desired_velocity -= (1.0 + tuning.wall_restitution) * normal_speed * normal;
+			self.desired_velocity *= 1.0 - tuning.wall_impact_speed_loss;
+		} else {
+			self.desired_velocity -= normal_speed * normal;
+		}
+		Some(impact_speed)
+	}
+
+	/// Integrates gravity, quadratic drag and the terminal velocity over a single step in the air
+	pub fn apply_air_physics(&mut self, gravity_factor: f32, tuning: &MovementTuning, dt: f32) {
+		self.desired_velocity -= dt * self.gravity * gravity_factor * self.up;
+		let speed = self.desired_velocity.length();
+		// Never let drag overshoot and flip the velocity around, no matter how large the step
+		let drag = f32::min(dt * tuning.air_drag * speed, 1.0);
+		self.desired_velocity -= drag * self.desired_velocity;
+		self.set_vertical_speed(f32::max(self.vertical(self.desired_velocity), -tuning.terminal_velocity));
+	}
+
+	/// Turns the velocity towards a direction by at most `max_turn`, keeping its speed [radian]
+	pub fn steer_towards(&mut self, direction: Vec3, max_turn: f32) {
+		let speed = self.desired_velocity.length();
+		if speed <= 0.1 || max_turn <= 0.0 {return;}
+		let current = self.desired_velocity / speed;
+		let angle = current.angle_between(direction);
+		let turn = Quat::from_rotation_arc(current, direction.normalize());
+		let fraction = if angle > max_turn {max_turn / angle} else {1.0};
+		self.desired_velocity = Quat::IDENTITY.slerp(turn, fraction) * self.desired_velocity;
+	}
+
+	/// Redirects the velocity as a momentum lock engages
+	pub fn engage_lock(&mut self, view_direction: Vec3, tuning: &MovementTuning) {
+		if tuning.momentum_lock_snap != MomentumLockSnap::View {return;}
+		// We snap it forwards
+		let mut free_velocity = self.desired_velocity;
+		let vertical_speed = self.vertical(free_velocity);
+		if vertical_speed < 0.0 {
+			// Downwards velocity is not very valuable
+			free_velocity -= (1.0 - tuning.locked_downward_velocity_factor) * vertical_speed * self.up;
+		}
+		self.desired_velocity = free_velocity.length() * view_direction;
+	}
+
+	/// Performs or cuts jumps based on the buffered input and ground contact history,
+	/// only ever touches the vertical component of the velocity, so jumping does not cost any horizontal speed
+	pub fn update_jump(&mut self, tuning: &MovementTuning, dt: f32) {
+		if self.ground_contact {
+			self.time_since_grounded = 0.0;
+		} else {
+			self.time_since_grounded += dt;
+		}
+
+		let can_jump = self.time_since_grounded <= tuning.coyote_time && !self.jumping;
+		if self.jump_buffer > 0.0 && can_jump {
+			self.set_vertical_speed(f32::max(self.vertical(self.desired_velocity), tuning.jump_speed));
+			self.jump_buffer = 0.0;
+			self.jumping = true;
+			// Spend the rest of the coyote time so it cannot be used for a second jump
+			self.time_since_grounded = tuning.coyote_time + dt;
+		} else if let Some(wall_normal) = self.wall_normal.filter(|_| self.jump_buffer > 0.0 && self.state == MovementState::WallRunning) {
+			// Reflect whatever velocity goes into the wall and push off of it
+			let into_wall = self.desired_velocity.dot(wall_normal);
+			if into_wall < 0.0 {
+				self.desired_velocity -= 2.0 * into_wall * wall_normal;
+			}
+			self.desired_velocity += tuning.wall_jump_push_speed * wall_normal;
+			self.set_vertical_speed(f32::max(self.vertical(self.desired_velocity), tuning.jump_speed));
+			self.jump_buffer = 0.0;
+			self.jumping = true;
+		} else {
+			self.jump_buffer = f32::max(self.jump_buffer - dt, 0.0);
+		}
+
+		if self.jumping {
+			let vertical_speed = self.vertical(self.desired_velocity);
+			if vertical_speed <= 0.0 {
+				self.jumping = false;
+			} else if !self.jump_held {
+				self.set_vertical_speed(tuning.jump_cut_factor * vertical_speed);
+				self.jumping = false;
+			}
+		}
+	}
 }
 
-#[derive(Resource, Clone, Copy, PartialEq, Debug, Default)]
+/// Statistics of a player's mouse movement, used to tell whether they are circling it
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
 pub struct MouseMovement {
 	/// An averaged speed value, roughly corresponding to how quickly mouse is moving around
 	pub average_speed: f32,
@@ -32,14 +308,45 @@ pub struct MouseMovement {
 	pub average_velocity: Vec2,
 }
 
+/// Where and in which direction the player is looking, the `CameraRig` watching the player is placed from it
 #[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
 pub struct PlayerCamera {
 	/// Angle between -pi/2 and +pi/2 measuring the pitch of the camera
 	pitch: f32
 }
 
+impl PlayerCamera {
+	/// Angle between -pi/2 and +pi/2 measuring the pitch of the camera
+	pub fn pitch(&self) -> f32 {
+		self.pitch
+	}
+
+	/// Points the camera straight ahead from a standing player's eyes again
+	pub fn reset(&mut self, camera_transform: &mut Transform) {
+		self.pitch = 0.0;
+		*camera_transform = Transform::from_translation(PLAYER_CAPSULE_HALF_HEIGHT * Vec3::Y);
+	}
+
+	/// Direction the camera is looking in, given the transform of the player it belongs to
+	pub fn view_direction(&self, player_transform: &Transform) -> Vec3 {
+		f32::cos(self.pitch) * player_transform.forward() +
+		f32::sin(self.pitch) * player_transform.up()
+	}
+}
+
+/// The simulated transform of the player at the last two fixed ticks,
+/// the rendered transform is interpolated between them
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
+pub struct PlayerInterpolation {
+	pub previous: Transform,
+	pub current: Transform,
+}
+
 #[derive(Resource, Clone, PartialEq, Eq, Hash, Debug, Default)]
-pub struct PlayerMesh(Handle<Mesh>);
+pub struct PlayerMesh {
+	capsule: Handle<Mesh>,
+	marble: Handle<Mesh>,
+}
 
 pub struct PlayerPlugin;
 
@@ -47,177 +354,464 @@ impl Plugin for PlayerPlugin {
 	fn build(&self, app: &mut App) {
 		app
 			.init_resource::<PlayerMesh>()
-			.init_resource::<MouseMovement>()
+			.add_event::<MovementStateChanged>()
+			.add_event::<PlayerImpact>()
 			.add_systems(Startup, load_player_mesh)
 			.add_systems(OnEnter(crate::state::GameState::InLevel), spawn_player)
 			.add_systems(Update, (
-				(process_mouse_movement, process_input_modifiers),
-				(rotate_player, player_move_input),
-				player_kinematics.before(PhysicsSet::SyncBackend)
-			).chain())
-			.add_systems(Update, read_result_system.after(PhysicsSet::Writeback))
+				apply_player_controller,
+				join_gamepad_players.run_if(in_state(crate::state::GameState::InLevel)),
+			))
+			// Everything that affects the simulation runs in a fixed, fully ordered sequence, so it plays out the same every time
+			.add_systems(FixedUpdate, (
+				restore_simulated_transform,
+				process_mouse_movement,
+				rotate_player,
+				process_input_modifiers,
+				update_crouch,
+				detect_ground,
+				update_movement_state,
+				player_move_input,
+				player_dash,
+				player_kinematics,
+			).chain().after(SamplePlayerInput).before(PhysicsSet::SyncBackend))
+			.add_systems(FixedUpdate, (
+				read_result_system,
+				record_simulated_transform,
+			).chain().after(PhysicsSet::Writeback))
+			.add_systems(PostUpdate, interpolate_rendered_transform.before(TransformSystem::TransformPropagate))
 		;
 	}
 }
 
+/// Collider of a player that isn't crouching
+pub fn standing_collider() -> Collider {
+	Collider::capsule_y(PLAYER_CAPSULE_HALF_HEIGHT, PLAYER_CAPSULE_RADIUS)
+}
+
 fn load_player_mesh(
 	mut meshes: ResMut<Assets<Mesh>>,
 	mut player_mesh: ResMut<PlayerMesh>,
 ) {
-	player_mesh.0 = meshes.add(bevy::render::mesh::shape::Capsule {
-		radius: 0.5,
+	player_mesh.capsule = meshes.add(bevy::render::mesh::shape::Capsule {
+		radius: PLAYER_CAPSULE_RADIUS,
 		rings: 2,
-		depth: 2.,
+		depth: 2.0 * PLAYER_CAPSULE_HALF_HEIGHT,
 		latitudes: 16,
 		longitudes: 5,
 		uv_profile: shape::CapsuleUvProfile::Aspect,
 	}.into());
+	player_mesh.marble = meshes.add(shape::UVSphere {
+		radius: MARBLE_RADIUS,
+		sectors: 16,
+		stacks: 8,
+	}.into());
 }
 
-fn spawn_player(
-	mut commands: Commands,
-	player_mesh: Res<PlayerMesh>,
-) {
-	commands.spawn((
-		PbrBundle {
-			mesh: player_mesh.0.clone(),
-			..Default::default()
-		},
-		(KinematicCharacterController {
+/// Everything `insert_capsule_body` adds, for taking it off again
+type CapsuleBody = (
+	Handle<Mesh>, Handle<StandardMaterial>, KinematicCharacterController, KinematicCharacterControllerOutput,
+	Friction, Collider, ColliderMassProperties,
+);
+
+/// Makes a player a capsule moved by a `KinematicCharacterController`
+fn insert_capsule_body(commands: &mut Commands, player: Entity, player_mesh: &PlayerMesh) {
+	commands.entity(player).insert((
+		player_mesh.capsule.clone(),
+		Handle::<StandardMaterial>::default(),
+		KinematicCharacterController {
 			offset: CharacterLength::Absolute(0.1),
 			slide: true,
-			apply_impulse_to_dynamic_bodies: true,
+			// Props are pushed by `push_props` instead, scaled with how fast we hit them
+			apply_impulse_to_dynamic_bodies: false,
 			..Default::default()
 		},
 		Friction {
 			coefficient: 0.1,
 			combine_rule: CoefficientCombineRule::Min,
 		},
-		Collider::capsule_y(1.0, 0.5), ColliderMassProperties::Mass(20.0),
-		Player, PlayerMovement::default())
-	)).with_children(|builder| {
+		standing_collider(), ColliderMassProperties::Mass(20.0),
+	));
+}
+
+/// Makes a player a marble rolled around by the physics engine
+fn insert_marble_body(commands: &mut Commands, player: Entity, player_mesh: &PlayerMesh, tuning: &MovementTuning) {
+	commands.entity(player).insert(marble_body(tuning)).with_children(|builder| {
 		builder.spawn((
-			PlayerCamera::default(),
-			Camera3dBundle {
-				transform: Transform::from_translation(Vec3::Y),
+			MarbleMesh,
+			PbrBundle {
+				mesh: player_mesh.marble.clone(),
 				..Default::default()
-			}
+			},
 		));
 	});
 }
 
-fn process_mouse_movement(
-	mut mouse_inputs: EventReader<MouseMotion>,
-	mut mouse_statistics: ResMut<MouseMovement>,
+fn spawn_player(
+	mut commands: Commands,
+	player_mesh: Res<PlayerMesh>,
+	tuning: Res<MovementTuning>,
 ) {
-	let mut dmouse: Vec2 = Vec2::ZERO;
+	spawn_local_player(&mut commands, &player_mesh, &tuning, InputSource::KeyboardMouse);
+}
 
-	for input in mouse_inputs.read() {
-		dmouse += input.delta;
+/// Spawns a player controlled from this machine along with the camera watching it
+fn spawn_local_player(commands: &mut Commands, player_mesh: &PlayerMesh, tuning: &MovementTuning, source: InputSource) {
+	let player = commands.spawn((
+		Player, PlayerMovement::default(), PlayerDash::default(), PlayerInterpolation::default(),
+		MouseMovement::default(), PlayerInput::default(), LocalInput { source }, SpatialBundle::default(),
+		Grapple::default(), LockOn::default(), PlayerIntegrity::new(&tuning.integrity), RespawnPoint::default(),
+	)).with_children(|builder| {
+		builder.spawn((
+			PlayerCamera::default(),
+			SpatialBundle::from_transform(Transform::from_translation(Vec3::Y)),
+		));
+	}).id();
+	match tuning.player_controller {
+		PlayerControllerKind::Capsule => insert_capsule_body(commands, player, player_mesh),
+		PlayerControllerKind::Marble => insert_marble_body(commands, player, player_mesh, tuning),
 	}
 
-	// Exponentially tracked moving average
-	let percentage = 0.05;
-	mouse_statistics.average_speed *= 1.0 - percentage;
-	mouse_statistics.average_velocity *= 1.0 - percentage;
-	mouse_statistics.average_speed += dmouse.length() * percentage;
-	mouse_statistics.average_velocity += dmouse * percentage;
+	commands.spawn((
+		CameraRig::new(player),
+		Camera3dBundle::default(),
+		// The HUD is drawn by the UI camera, laid out over this player's part of the window
+		UiCameraConfig { show_ui: false },
+	));
 }
 
-fn process_input_modifiers(
-	mut player: Query<&mut PlayerMovement>,
-	keyboard_inputs: Res<Input<KeyCode>>,
-	input_map: Res<InputMap>,
+/// Lets a gamepad nobody plays with yet join the level as another local player,
+/// and takes players out of it again along with their camera once their gamepad disconnects
+fn join_gamepad_players(
+	mut commands: Commands,
+	players: Query<(Entity, &LocalInput), With<Player>>,
+	rigs: Query<(Entity, &CameraRig)>,
+	gamepads: Res<Gamepads>,
+	buttons: Res<Input<GamepadButton>>,
+	gamepad_map: Res<GamepadMap>,
+	player_mesh: Res<PlayerMesh>,
+	tuning: Res<MovementTuning>,
+) {
+	for (player, local_input) in players.iter() {
+		let InputSource::Gamepad(gamepad) = local_input.source else {continue;};
+		if gamepads.contains(gamepad) {continue;}
+		commands.entity(player).despawn_recursive();
+		for (rig, camera_rig) in rigs.iter() {
+			if camera_rig.player == player {
+				commands.entity(rig).despawn_recursive();
+			}
+		}
+	}
+
+	for gamepad in gamepads.iter() {
+		if !buttons.just_pressed(GamepadButton::new(gamepad, gamepad_map.join)) {continue;}
+		let source = InputSource::Gamepad(gamepad);
+		if players.iter().any(|(_, local_input)| local_input.source == source) {continue;}
+		spawn_local_player(&mut commands, &player_mesh, &tuning, source);
+	}
+}
+
+/// Swaps the body of players whose controller doesn't match the tuning anymore,
+/// the tuning asset usually finishes loading only after the level has spawned its players, and levels can bring their own
+fn apply_player_controller(
+	mut commands: Commands,
+	mut players: Query<(Entity, &mut PlayerMovement, Has<Marble>, Option<&Children>), With<Player>>,
+	marble_meshes: Query<(), With<MarbleMesh>>,
+	player_mesh: Res<PlayerMesh>,
+	tuning: Res<MovementTuning>,
 ) {
-	let lock_pressed = keyboard_inputs.just_pressed(input_map.velocity_lock);
-	for mut player in player.iter_mut() {
-		// Switch
-		player.locked_velocity ^= lock_pressed;
-		// Engage
-		player.just_locked_velocity = player.locked_velocity & lock_pressed;
+	if !tuning.is_changed() {return;}
+	let wants_marble = tuning.player_controller == PlayerControllerKind::Marble;
+	for (player, mut movement, is_marble, children) in players.iter_mut() {
+		if is_marble == wants_marble {continue;}
+		// Neither body can take over a crouch, the capsule comes back standing
+		movement.crouching = false;
+		if is_marble {
+			commands.entity(player).remove::<MarbleBody>();
+			for child in children.into_iter().flatten().filter(|child| marble_meshes.contains(**child)) {
+				commands.entity(*child).despawn_recursive();
+			}
+			insert_capsule_body(&mut commands, player, &player_mesh);
+		} else {
+			commands.entity(player).remove::<CapsuleBody>();
+			insert_marble_body(&mut commands, player, &player_mesh, &tuning);
+		}
 	}
 }
 
-fn rotate_player(
-	mut player: Query<&mut Transform, With<Player>>,
-	mut camera: Query<(&mut Transform, &mut PlayerCamera), Without<Player>>,
-	mut mouse_inputs: EventReader<MouseMotion>,
+/// Puts the last simulated transform back in place of the interpolated one before simulating further
+pub(crate) fn restore_simulated_transform(
+	mut player: Query<(&mut Transform, &mut PlayerInterpolation), With<Player>>,
+) {
+	for (mut transform, mut interpolation) in player.iter_mut() {
+		*transform = interpolation.current;
+		interpolation.previous = interpolation.current;
+	}
+}
+
+fn record_simulated_transform(
+	mut player: Query<(&Transform, &mut PlayerInterpolation), With<Player>>,
+) {
+	for (transform, mut interpolation) in player.iter_mut() {
+		interpolation.current = *transform;
+	}
+}
+
+pub(crate) fn interpolate_rendered_transform(
+	mut player: Query<(&mut Transform, &PlayerInterpolation), With<Player>>,
+	fixed_time: Res<Time<Fixed>>,
+) {
+	let t = fixed_time.overstep_percentage();
+	for (mut transform, interpolation) in player.iter_mut() {
+		transform.translation = interpolation.previous.translation.lerp(interpolation.current.translation, t);
+		transform.rotation = interpolation.previous.rotation.slerp(interpolation.current.rotation, t);
+		transform.scale = interpolation.current.scale;
+	}
+}
+
+pub(crate) fn process_mouse_movement(
+	mut player: Query<(Entity, &PlayerInput, &mut MouseMovement), With<Player>>,
+	rigs: Query<&CameraRig>,
+) {
+	for (entity, player_input, mut mouse_statistics) in player.iter_mut() {
+		// The mouse steers the free camera while spectating, circling it must not accelerate the player left behind
+		let spectating = rigs.iter().any(|rig| rig.player == entity && rig.mode == CameraMode::Spectator);
+		let dmouse = if spectating {Vec2::ZERO} else {player_input.mouse_delta};
+
+		// Exponentially tracked moving average
+		let percentage = 0.05;
+		mouse_statistics.average_speed *= 1.0 - percentage;
+		mouse_statistics.average_velocity *= 1.0 - percentage;
+		mouse_statistics.average_speed += dmouse.length() * percentage;
+		mouse_statistics.average_velocity += dmouse * percentage;
+	}
+}
+
+/// Finds the view of a player among all the `PlayerCamera`s
+fn find_view<'a>(player: Entity, cameras: impl IntoIterator<Item = (&'a PlayerCamera, &'a Parent)>) -> Option<&'a PlayerCamera> {
+	cameras.into_iter().find(|(_, parent)| parent.get() == player).map(|(camera, _)| camera)
+}
+
+/// Finds where a player looks from and towards among all the `PlayerCamera`s,
+/// from the simulated transforms rather than global ones, those are only up to date with the rendered frame
+pub(crate) fn find_eye_and_view<'a>(
+	player: Entity,
+	player_transform: &Transform,
+	cameras: impl IntoIterator<Item = (&'a Transform, &'a PlayerCamera, &'a Parent)>,
+) -> Option<(Vec3, Vec3)> {
+	let (camera_transform, camera, _) = cameras.into_iter().find(|(_, _, parent)| parent.get() == player)?;
+	Some((player_transform.transform_point(camera_transform.translation), camera.view_direction(player_transform)))
+}
+
+fn process_input_modifiers(
+	mut player: Query<(Entity, &Transform, &PlayerInput, &mut PlayerMovement), With<Player>>,
+	camera: Query<(&PlayerCamera, &Parent), Without<Player>>,
 	input_config: Res<InputSettings>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+	mut state_events: EventWriter<MovementStateChanged>,
 ) {
-	let mut dmouse: Vec2 = Vec2::ZERO;
+	let dt = time.delta_seconds();
+	for (entity, transform, player_input, mut player) in player.iter_mut() {
+		let Some(camera_angle) = find_view(entity, camera.iter()) else {continue;};
+		let lock_pressed = player_input.velocity_lock_pressed;
+		let lock_held = player_input.velocity_lock_held;
+		let jump_pressed = player_input.jump_pressed;
+		let jump_held = player_input.jump_held;
+		let crouch_held = player_input.crouch_held;
+		let was_locked = player.state == MovementState::Locked;
+		let wants_lock = if input_config.hold_to_lock {
+			// A lock that ran out has to be pressed again, holding the key shouldn't keep re-engaging it
+			lock_held && (was_locked || lock_pressed)
+		} else {
+			// Switch
+			was_locked ^ lock_pressed
+		};
+		let budget_left = tuning.momentum_lock_time_budget.is_none_or(|budget| player.lock_time_used < budget);
+		let locked = wants_lock && budget_left;
+		let transition = if locked && !was_locked {
+			// Engage
+			player.engage_lock(camera_angle.view_direction(transform), &tuning);
+			player.transition(entity, MovementState::Locked)
+		} else if !locked && was_locked {
+			let free_state = player.free_state(&tuning);
+			player.transition(entity, free_state)
+		} else {
+			None
+		};
+		if let Some(event) = transition {
+			state_events.send(event);
+		}
+		if locked {
+			player.lock_time_used += dt;
+		} else {
+			player.lock_time_used = f32::max(player.lock_time_used - tuning.momentum_lock_budget_regen * dt, 0.0);
+		}
 
-	for input in mouse_inputs.read() {
-		dmouse += input.delta;
+		if jump_pressed {
+			player.jump_buffer = tuning.jump_buffer_time;
+		}
+		player.jump_held = jump_held;
+		player.crouch_held = crouch_held;
 	}
+}
 
-	// Invert y
-	dmouse.y *= -1.0;
-	// TODO: Figure out sensitivity
-	let delta_angle = dmouse * input_config.camera_sensitivity;
+/// Resizes the collider and moves the camera when crouching or standing up, standing up is refused while there's no headroom
+fn update_crouch(
+	mut player: Query<(Entity, &mut Transform, &mut Collider, &mut PlayerMovement, Has<Marble>), With<Player>>,
+	mut camera: Query<(&mut Transform, &Parent), (With<PlayerCamera>, Without<Player>)>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+) {
+	for (entity, mut transform, mut collider, mut player, is_marble) in player.iter_mut() {
+		let height_difference = PLAYER_CAPSULE_HALF_HEIGHT - tuning.crouch_half_height;
+		// Marbles have nothing to crouch with
+		let crouch_held = player.crouch_held && !is_marble;
+		if crouch_held && !player.crouching {
+			player.crouching = true;
+			*collider = Collider::capsule_y(tuning.crouch_half_height, PLAYER_CAPSULE_RADIUS);
+			// Keep our feet on the ground, in the air we just pull them up
+			if player.ground_contact {
+				transform.translation -= height_difference * player.up;
+			}
+		} else if !crouch_held && player.crouching {
+			// On the ground our head has to rise twice as much, since our feet stay put
+			let headroom_needed = if player.ground_contact {2.0 * height_difference} else {height_difference};
+			let ceiling = rapier_context.cast_shape(
+				transform.translation,
+				transform.rotation,
+				player.up,
+				&collider,
+				headroom_needed,
+				true,
+				QueryFilter::default().exclude_collider(entity).exclude_sensors(),
+			);
+			if ceiling.is_none() {
+				player.crouching = false;
+				*collider = standing_collider();
+				if player.ground_contact {
+					transform.translation += height_difference * player.up;
+				}
+			}
+		}
+
+		let eye_height = player.capsule_half_height(&tuning);
+		for (mut camera_transform, parent) in camera.iter_mut() {
+			if parent.get() == entity {
+				camera_transform.translation.y = eye_height;
+			}
+		}
+	}
+}
 
-	if delta_angle.length_squared() > 0.00001 {
-		for mut transform in player.iter_mut() {
+/// Moves players between the states not governed by input, based on their contacts and speed
+pub(crate) fn update_movement_state(
+	mut player: Query<(Entity, &mut PlayerMovement), With<Player>>,
+	tuning: Res<MovementTuning>,
+	mut state_events: EventWriter<MovementStateChanged>,
+) {
+	for (entity, mut player) in player.iter_mut() {
+		if player.ground_contact {
+			player.time_wall_running = 0.0;
+		}
+		if player.state == MovementState::Locked {continue;}
+
+		let next_state = player.free_state(&tuning);
+		if next_state == MovementState::WallRunning && player.state != MovementState::WallRunning {
+			// Catch the player so a run started while falling doesn't immediately slide off
+			let vertical_speed = player.vertical(player.desired_velocity);
+			player.set_vertical_speed(f32::max(vertical_speed, 0.0));
+		}
+		if let Some(event) = player.transition(entity, next_state) {
+			state_events.send(event);
+		}
+	}
+}
+
+/// Turns the player and its view with the mouse, or the free camera instead while the player's rig is spectating
+pub(crate) fn rotate_player(
+	mut player: Query<(Entity, &mut Transform, &PlayerInput), With<Player>>,
+	mut camera: Query<(&mut Transform, &mut PlayerCamera, &Parent), Without<Player>>,
+	mut rigs: Query<&mut CameraRig>,
+	input_config: Res<InputSettings>,
+) {
+	for (entity, mut transform, player_input) in player.iter_mut() {
+		let mut dmouse = player_input.mouse_delta;
+
+		// Invert y
+		dmouse.y *= -1.0;
+		// TODO: Figure out sensitivity
+		let delta_angle = dmouse * input_config.camera_sensitivity;
+
+		if delta_angle.length_squared() > 0.00001 {
+			if let Some(mut rig) = rigs.iter_mut().find(|rig| rig.player == entity) {
+				if rig.mode == CameraMode::Spectator {
+					rig.rotate_spectator(delta_angle);
+					continue;
+				}
+			}
 			// Rotate player
 			transform.rotate_local_y(-delta_angle.x);
-		}
-		for (mut transform, mut camera) in camera.iter_mut() {
-			// Rotate camera
-			camera.pitch = f32::clamp(camera.pitch + delta_angle.y, -PI/2.0, PI/2.0) ;
-			transform.rotation = Quat::from_rotation_x(camera.pitch);
+			for (mut camera_transform, mut camera, parent) in camera.iter_mut() {
+				if parent.get() != entity {continue;}
+				// Rotate camera
+				camera.pitch = f32::clamp(camera.pitch + delta_angle.y, -PI/2.0, PI/2.0) ;
+				camera_transform.rotation = Quat::from_rotation_x(camera.pitch);
+			}
 		}
 	}
 }
 
-fn player_move_input(
-	mut player: Query<(&Transform, &mut PlayerMovement), With<Player>>,
-	camera: Query<&PlayerCamera, Without<Player>>,
-	mouse_data: Res<MouseMovement>,
+pub(crate) fn player_move_input(
+	mut player: Query<(Entity, &Transform, &MouseMovement, &mut PlayerMovement), With<Player>>,
+	camera: Query<(&PlayerCamera, &Parent), Without<Player>>,
+	tuning: Res<MovementTuning>,
 	time: Res<Time>,
 ) {
-	let Ok(camera_angle) = camera.get_single() else {return;};
 	let dt = time.delta_seconds();
 
-	let max_acceleration = 15.0;
-	let min_acceleration = 3.0;
-	let acceleration = {
-		let t = 0.5 * camera_angle.pitch / PI;
-		max_acceleration * t + min_acceleration * (1.0 - t)
-	};
-
+	let max_acceleration = tuning.max_acceleration;
+	let min_acceleration = tuning.min_acceleration;
 	let min_speed = dt * 300.0;
 
-	// Gotta check we are circling with cursor
-	let player_is_trying_to_accelerate = mouse_data.average_speed > mouse_data.average_velocity.length() && mouse_data.average_speed > min_speed;
+	for (entity, transform, mouse_data, mut player) in player.iter_mut() {
+		let Some(camera_angle) = find_view(entity, camera.iter()) else {continue;};
+		let acceleration = {
+			let t = 0.5 * camera_angle.pitch / PI;
+			max_acceleration * t + min_acceleration * (1.0 - t)
+		};
 
-	for (transform, mut player) in player.iter_mut() {
-		// If the player locks their velocity, we snap it forwards
-		if player.just_locked_velocity {
-			let mut free_velocity = player.desired_velocity;
-			if free_velocity.y < 0.0 {
-				// Downwards velocity is not very valuable
-				free_velocity.y *= 0.1;
-			}
-			let free_speed = free_velocity.length();
-			let directly_in_front_of_my_face = 
-				f32::cos(camera_angle.pitch) * transform.forward() +
-				f32::sin(camera_angle.pitch) * transform.up()
-			;
-			player.desired_velocity = free_speed * directly_in_front_of_my_face;
+		// Gotta check we are circling with cursor
+		let player_is_trying_to_accelerate = mouse_data.average_speed > mouse_data.average_velocity.length() && mouse_data.average_speed > min_speed;
+
+		player.accelerating = player_is_trying_to_accelerate;
+		// If the player locked their velocity, then we do not change it in movement control code,
+		// save for the slow decay and steering some tunings allow
+		if player.state == MovementState::Locked {
+			player.desired_velocity *= tuning.momentum_lock_velocity_decay.powf(dt);
+			player.steer_towards(camera_angle.view_direction(transform), tuning.momentum_lock_steering * dt);
+			continue;
+		}
+		// Slides ignore steering and only slowly bleed speed, slopes are handled in kinematics
+		if player.state == MovementState::Sliding {
+			player.desired_velocity *= tuning.slide_velocity_decay.powf(dt);
+			continue;
 		}
-		// If the player locked their velocity, then we do not change it in movement control code
-		if player.locked_velocity {continue;}
+
+		let in_air = matches!(player.state, MovementState::Airborne | MovementState::WallRunning);
+		let control = if in_air {tuning.air_control} else {1.0};
 
 		if player_is_trying_to_accelerate {
-			let min_time_acceleration = 0.5;
+			let min_time_acceleration = tuning.min_acceleration_time;
 			if player.time_accelerating < min_time_acceleration {
 				player.time_accelerating = min_time_acceleration;
-				player.desired_velocity += min_time_acceleration * acceleration * transform.forward()
+				player.desired_velocity += control * min_time_acceleration * acceleration * transform.forward()
 			}
 			player.time_accelerating += dt;
-			player.desired_velocity += transform.forward() * dt * acceleration;
+			player.desired_velocity += transform.forward() * dt * acceleration * control;
 			// Rotate velocity vector towards transform.forward
-			let axis = player.desired_velocity.cross(transform.forward()).y * transform.up();
-			let delta = dt * player.desired_velocity.cross(axis);
+			let axis = player.desired_velocity.cross(transform.forward()).dot(transform.up()) * transform.up();
+			let delta = control * dt * player.desired_velocity.cross(axis);
 			// Ultra yee-yee ass rotation, might work tho
 			let speed = player.desired_velocity.length();
 			if speed > 0.1 {
@@ -227,32 +821,337 @@ fn player_move_input(
 			player.desired_velocity = player.desired_velocity.clamp_length_max(player.time_accelerating * max_acceleration)
 		} else {
 			// TODO: Add some sort of coyote-time to various actions to help conserve speed when desirable
-			player.time_accelerating *= 0.9;
-			player.desired_velocity *= 0.95;
+			// Per-second fractions, so the decay doesn't depend on the tick rate
+			player.time_accelerating *= tuning.acceleration_time_decay.powf(dt);
+			if in_air {
+				// Falling is left to gravity, air drag and terminal velocity
+				let horizontal = player.horizontal(player.desired_velocity);
+				player.desired_velocity -= (1.0 - tuning.air_velocity_decay.powf(dt)) * horizontal;
+			} else {
+				player.desired_velocity *= tuning.ground_velocity_decay.powf(dt);
+			}
+		}
+	}
+}
+
+pub(crate) fn player_dash(
+	mut player: Query<(Entity, &Transform, &PlayerInput, &mut PlayerMovement, &mut PlayerDash), With<Player>>,
+	camera: Query<(&PlayerCamera, &Parent), Without<Player>>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+) {
+	let dt = time.delta_seconds();
+
+	for (entity, transform, player_input, mut player, mut dash) in player.iter_mut() {
+		let Some(camera_angle) = find_view(entity, camera.iter()) else {continue;};
+		let dash_pressed = player_input.dash_pressed;
+
+		dash.cooldown = f32::max(dash.cooldown - dt, 0.0);
+		if player.accelerating {
+			dash.energy = f32::min(dash.energy + tuning.dash_energy_regen * dt, tuning.dash_max_energy);
+		}
+
+		if dash_pressed && dash.cooldown <= 0.0 && dash.energy >= tuning.dash_energy_cost {
+			dash.energy -= tuning.dash_energy_cost;
+			dash.cooldown = tuning.dash_cooldown;
+			player.desired_velocity += tuning.dash_speed * camera_angle.view_direction(transform);
 		}
 	}
 }
 
-fn player_kinematics(
+pub(crate) fn player_kinematics(
 	mut player: Query<(&mut PlayerMovement, &mut KinematicCharacterController), With<Player>>,
+	tuning: Res<MovementTuning>,
 	time: Res<Time>,
 ) {
 	let dt = time.delta_seconds();
 	for (mut player, mut kinematic_body) in player.iter_mut() {
 		//println!("{:?}", player.desired_velocity);
-		if player.grounded {
-			if player.desired_velocity.y <= 0.0 {
-				player.desired_velocity.y = 0.01;
+		let max_slope = tuning.max_walkable_slope_degrees.to_radians();
+		kinematic_body.max_slope_climb_angle = max_slope;
+		kinematic_body.min_slope_slide_angle = max_slope;
+		kinematic_body.up = player.up;
+		let on_ground = match player.state {
+			MovementState::Grounded | MovementState::Sliding => true,
+			MovementState::Locked => player.on_walkable_ground(&tuning),
+			MovementState::Airborne | MovementState::WallRunning => false,
+		};
+		if on_ground {
+			match player.ground_normal {
+				// Don't glue the player to the ground while they are moving off of it, a cut jump still is even though `jumping` is over
+				Some(ground_normal) if !player.jumping && player.desired_velocity.dot(ground_normal) <= GROUND_SEPARATION_SPEED => {
+					player.follow_slope(ground_normal, &tuning, dt);
+				},
+				_ => if player.vertical(player.desired_velocity) <= 0.0 {
+					player.set_vertical_speed(0.01);
+				},
+			}
+		} else if player.state == MovementState::WallRunning {
+			player.apply_air_physics(tuning.wall_run_gravity_factor, &tuning, dt);
+			player.time_wall_running += dt;
+			if let Some(wall_normal) = player.wall_normal {
+				let into_wall = f32::min(player.desired_velocity.dot(wall_normal), 0.0);
+				player.desired_velocity -= into_wall * wall_normal;
 			}
 		} else {
-			player.desired_velocity.y -= dt * 9.81;
+			player.apply_air_physics(1.0, &tuning, dt);
+		}
+		player.update_jump(&tuning, dt);
+		let mut translation = (player.desired_velocity + player.ground_velocity) * dt;
+		if let Some(wall_normal) = player.wall_normal.filter(|_| player.state == MovementState::WallRunning) {
+			// The controller only reports walls we move into, lean in so we keep touching it
+			translation -= tuning.wall_stick_speed * dt * wall_normal;
+		}
+		kinematic_body.translation = Some(translation);
+	}
+}
+
+pub(crate) fn detect_ground(
+	mut player: Query<(Entity, &Transform, &mut PlayerMovement, Has<Marble>), With<Player>>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+) {
+	for (entity, transform, mut player, is_marble) in player.iter_mut() {
+		let feet_distance = if is_marble {MARBLE_RADIUS} else {player.capsule_half_height(&tuning) + PLAYER_CAPSULE_RADIUS};
+		let ground = rapier_context.cast_ray_and_get_normal(
+			transform.translation,
+			-player.up,
+			feet_distance + tuning.ground_probe_distance,
+			true,
+			QueryFilter::default().exclude_collider(entity).exclude_sensors(),
+		);
+		player.ground_normal = ground.map(|(_, intersection)| intersection.normal);
+		player.ground_collider = ground.map(|(collider, _)| collider);
+		if is_marble {
+			// No character controller tells a marble whether it's touching ground, so the probe has to
+			player.ground_contact = ground.is_some_and(|(_, intersection)| intersection.toi <= MARBLE_RADIUS + MARBLE_CONTACT_TOLERANCE);
 		}
-		kinematic_body.translation = Some(player.desired_velocity * dt);
 	}
 }
 
-fn read_result_system(mut controllers: Query<(&mut PlayerMovement, &KinematicCharacterControllerOutput)>) {
-	for (mut player, output) in controllers.iter_mut() {
-		player.grounded = output.grounded;
+/// Mass of a player, which always has it set directly on its collider [kg]
+pub(crate) fn player_mass(mass_properties: &ColliderMassProperties) -> f32 {
+	match mass_properties {
+		ColliderMassProperties::Mass(mass) => *mass,
+		_ => 1.0,
+	}
+}
+
+/// Whether a collider belongs to a dynamic body, which players push around rather than bounce off of, see `push_props`
+pub(crate) fn is_prop(collider: Entity, bodies: &Query<&RigidBody>, rapier_context: &RapierContext) -> bool {
+	let body = rapier_context.collider_parent(collider).unwrap_or(collider);
+	bodies.get(body).is_ok_and(|rigid_body| *rigid_body == RigidBody::Dynamic)
+}
+
+pub(crate) fn read_result_system(
+	mut controllers: Query<(Entity, &mut PlayerMovement, &KinematicCharacterControllerOutput)>,
+	bodies: Query<&RigidBody>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+	mut impact_events: EventWriter<PlayerImpact>,
+) {
+	let max_slope = tuning.max_walkable_slope_degrees.to_radians();
+	for (entity, mut player, output) in controllers.iter_mut() {
+		player.ground_contact = output.grounded;
+		// Stop pushing into whatever we can't stand on, walkable ground is taken care of by slope following
+		for collision in output.collisions.iter() {
+			// Only missing when already overlapping, there is nothing to bounce off of then
+			let Some(normal) = collision.toi.details.map(|details| details.normal1) else {continue;};
+			if normal.angle_between(player.up) <= max_slope {continue;}
+			if is_prop(collision.entity, &bodies, &rapier_context) {continue;}
+			let Some(impact_speed) = player.collide(normal, &tuning) else {continue;};
+			if impact_speed >= tuning.impact_event_min_speed {
+				impact_events.send(PlayerImpact { player: entity, surface: collision.entity, normal, impact_speed });
+			}
+		}
+		// The most vertical surface we hit is the best wall candidate
+		let up = player.up;
+		player.wall_normal = output.collisions.iter()
+			.filter_map(|collision| collision.toi.details.map(|details| details.normal1))
+			.filter(|normal| normal.dot(up).abs() <= tuning.wall_max_normal_y)
+			.min_by(|a, b| a.dot(up).abs().total_cmp(&b.dot(up).abs()));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::testing;
+
+	const DT: f32 = 1.0 / 64.0;
+
+	fn airborne(time_since_grounded: f32) -> PlayerMovement {
+		PlayerMovement {
+			state: MovementState::Airborne,
+			time_since_grounded,
+			..Default::default()
+		}
+	}
+
+	fn press_jump(movement: &mut PlayerMovement, tuning: &MovementTuning) {
+		movement.jump_buffer = tuning.jump_buffer_time;
+		movement.jump_held = true;
+	}
+
+	fn falling(gravity: f32, velocity: Vec3) -> PlayerMovement {
+		PlayerMovement {
+			desired_velocity: velocity,
+			gravity,
+			..airborne(1.0)
+		}
+	}
+
+	#[test]
+	fn drag_never_flips_velocity() {
+		let tuning = MovementTuning {air_drag: 1.0, ..Default::default()};
+		let initial = Vec3::new(0.0, 0.0, 100.0);
+		let mut movement = falling(0.0, initial);
+		movement.apply_air_physics(1.0, &tuning, 5.0);
+		assert!(movement.desired_velocity.dot(initial) >= 0.0);
+	}
+
+	#[test]
+	fn terminal_velocity_clamps_fall() {
+		let tuning = MovementTuning {air_drag: 0.0, ..Default::default()};
+		let mut movement = falling(9.81, Vec3::new(5.0, -2.0 * tuning.terminal_velocity, 0.0));
+		movement.apply_air_physics(1.0, &tuning, DT);
+		assert!((movement.vertical(movement.desired_velocity) + tuning.terminal_velocity).abs() < 1e-4);
+		assert_eq!(movement.desired_velocity.x, 5.0);
+	}
+
+	#[test]
+	fn free_fall_matches_closed_form() {
+		let tuning = MovementTuning {air_drag: 0.0, ..Default::default()};
+		let mut movement = falling(9.81, Vec3::ZERO);
+		let ticks = 64;
+		for _ in 0..ticks {
+			movement.apply_air_physics(1.0, &tuning, DT);
+		}
+		let expected = -9.81 * ticks as f32 * DT;
+		assert!((movement.vertical(movement.desired_velocity) - expected).abs() < 1e-4);
+	}
+
+	#[test]
+	fn drag_matches_closed_form() {
+		let tuning = MovementTuning::default();
+		let initial_speed = 30.0;
+		let mut movement = falling(0.0, Vec3::new(initial_speed, 0.0, 0.0));
+		let ticks = 128;
+		for _ in 0..ticks {
+			movement.apply_air_physics(1.0, &tuning, DT);
+		}
+		// dv/dt = -k * v^2 solves to v = v0 / (1 + k * v0 * t)
+		let expected = initial_speed / (1.0 + tuning.air_drag * initial_speed * ticks as f32 * DT);
+		let speed = movement.desired_velocity.length();
+		assert!((speed - expected).abs() / expected < 0.01, "{speed} differs from {expected}");
+	}
+
+	#[test]
+	fn idle_fall_follows_air_physics() {
+		let mut app = testing::simulation_app();
+		let player = testing::enter_level(&mut app);
+		testing::place_player(&mut app, player, Vec3::new(0.0, 400.0, 0.0));
+		let ticks = 256;
+		for _ in 0..ticks {
+			testing::step(&mut app);
+		}
+		let movement = app.world.get::<PlayerMovement>(player).unwrap();
+
+		let tuning = MovementTuning::default();
+		let mut expected = falling(movement.gravity, Vec3::ZERO);
+		for _ in 0..ticks {
+			expected.apply_air_physics(1.0, &tuning, DT);
+		}
+		let speed = movement.vertical(movement.desired_velocity);
+		let expected_speed = expected.vertical(expected.desired_velocity);
+		// Well past the 15 m/s the air velocity decay used to level the fall off at
+		assert!(expected_speed < -30.0);
+		assert!((speed - expected_speed).abs() < 0.1, "fell at {speed} m/s instead of {expected_speed} m/s");
+	}
+
+	#[test]
+	fn jump_within_coyote_time() {
+		let tuning = MovementTuning::default();
+		let mut movement = airborne(tuning.coyote_time - 2.0 * DT);
+		press_jump(&mut movement, &tuning);
+		movement.update_jump(&tuning, DT);
+		assert_eq!(movement.vertical(movement.desired_velocity), tuning.jump_speed);
+		assert!(movement.jumping);
+		assert_eq!(movement.jump_buffer, 0.0);
+	}
+
+	#[test]
+	fn no_jump_after_coyote_time() {
+		let tuning = MovementTuning::default();
+		let mut movement = airborne(tuning.coyote_time + DT);
+		press_jump(&mut movement, &tuning);
+		movement.update_jump(&tuning, DT);
+		assert_eq!(movement.desired_velocity, Vec3::ZERO);
+		assert!(!movement.jumping);
+		assert_eq!(movement.jump_buffer, tuning.jump_buffer_time - DT);
+	}
+
+	#[test]
+	fn buffered_jump_fires_on_landing() {
+		let tuning = MovementTuning::default();
+		let mut movement = airborne(1.0);
+		press_jump(&mut movement, &tuning);
+		movement.update_jump(&tuning, DT);
+		assert!(!movement.jumping);
+
+		movement.ground_contact = true;
+		movement.update_jump(&tuning, DT);
+		assert_eq!(movement.vertical(movement.desired_velocity), tuning.jump_speed);
+		assert!(movement.jumping);
+	}
+
+	#[test]
+	fn buffered_jump_expires() {
+		let tuning = MovementTuning::default();
+		let mut movement = airborne(1.0);
+		press_jump(&mut movement, &tuning);
+		let ticks = (tuning.jump_buffer_time / DT).ceil() as usize + 1;
+		for _ in 0..ticks {
+			movement.update_jump(&tuning, DT);
+		}
+
+		movement.ground_contact = true;
+		movement.update_jump(&tuning, DT);
+		assert!(!movement.jumping);
+		assert_eq!(movement.desired_velocity, Vec3::ZERO);
+	}
+
+	#[test]
+	fn releasing_jump_cuts_it() {
+		let tuning = MovementTuning::default();
+		let mut movement = airborne(1.0);
+		movement.jumping = true;
+		movement.set_vertical_speed(tuning.jump_speed);
+		movement.desired_velocity.x = 3.0;
+		movement.update_jump(&tuning, DT);
+		assert_eq!(movement.vertical(movement.desired_velocity), tuning.jump_cut_factor * tuning.jump_speed);
+		assert_eq!(movement.desired_velocity.x, 3.0);
+		assert!(!movement.jumping);
+	}
+
+	#[test]
+	fn no_double_jump_from_leftover_coyote_time() {
+		let tuning = MovementTuning::default();
+		let mut movement = PlayerMovement {ground_contact: true, ..Default::default()};
+		press_jump(&mut movement, &tuning);
+		movement.update_jump(&tuning, DT);
+		assert!(movement.jumping);
+
+		// Leave the ground and cut the jump right away, the coyote time left over must not allow another one
+		movement.ground_contact = false;
+		movement.jump_held = false;
+		movement.update_jump(&tuning, DT);
+		assert!(!movement.jumping);
+		let vertical_speed = movement.vertical(movement.desired_velocity);
+
+		press_jump(&mut movement, &tuning);
+		movement.update_jump(&tuning, DT);
+		assert!(!movement.jumping);
+		assert_eq!(movement.vertical(movement.desired_velocity), vertical_speed);
 	}
 }
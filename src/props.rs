@@ -0,0 +1,109 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::input::SamplePlayerInput;
+use crate::marble::{MarbleContacts, read_marble_contacts};
+use crate::player::{Player, PlayerMovement, player_mass};
+use crate::tuning::MovementTuning;
+
+pub struct PropsPlugin;
+
+/// Sent when a player runs into a dynamic body and pushes it
+#[derive(Event, Clone, Copy, PartialEq, Debug)]
+pub struct PropHit {
+	pub player: Entity,
+	/// Rigid body that got pushed
+	pub prop: Entity,
+	/// Impulse the prop received [kg*m/s]
+	pub impulse: Vec3,
+	/// Speed the player was moving into the prop at [m/s]
+	pub impact_speed: f32,
+	/// Whether the player was fast enough to send the prop flying
+	pub smashed: bool,
+}
+
+impl Plugin for PropsPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_event::<PropHit>()
+			.add_systems(FixedUpdate, add_impulse_to_dynamic_bodies.before(SamplePlayerInput))
+			.add_systems(FixedUpdate, push_props.after(PhysicsSet::Writeback).after(read_marble_contacts))
+		;
+	}
+}
+
+/// Gives every dynamic body an `ExternalImpulse` up front, so everything pushing it can add to the same one,
+/// inserting one through `Commands` would overwrite whatever someone else inserted in the same tick
+pub(crate) fn add_impulse_to_dynamic_bodies(
+	mut commands: Commands,
+	bodies: Query<(Entity, &RigidBody), Without<ExternalImpulse>>,
+) {
+	for (entity, rigid_body) in bodies.iter() {
+		if *rigid_body != RigidBody::Dynamic {continue;}
+		commands.entity(entity).insert(ExternalImpulse::default());
+	}
+}
+
+/// Gives dynamic bodies the player ran into a push that grows with the player's speed and mass
+fn push_props(
+	players: Query<(Entity, Option<&KinematicCharacterControllerOutput>, Option<&MarbleContacts>, &ColliderMassProperties, &PlayerMovement), With<Player>>,
+	mut impulses: Query<&mut ExternalImpulse>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+	mut hit_events: EventWriter<PropHit>,
+) {
+	let dt = time.delta_seconds();
+	if dt <= 0.0 {return;}
+	for (player, output, marble_contacts, mass_properties, movement) in players.iter() {
+		let player_mass = player_mass(mass_properties);
+		// A marble is a body of its own, the physics engine already pushed what it ran into
+		let pushed_by_physics = marble_contacts.is_some();
+		let (velocity, contacts): (Vec3, Vec<(Entity, Vec3)>) = match (output, marble_contacts) {
+			(Some(output), _) => (
+				output.desired_translation / dt,
+				output.collisions.iter()
+					.filter_map(|collision| Some((collision.entity, collision.toi.details?.normal1)))
+					.collect(),
+			),
+			(None, Some(marble_contacts)) => (movement.desired_velocity + movement.ground_velocity, marble_contacts.0.clone()),
+			(None, None) => continue,
+		};
+
+		// The controller can report the same body several times a tick, only the hardest hit counts
+		let mut hits: Vec<(Entity, Vec3, f32)> = Vec::new();
+		for (collider, normal) in contacts {
+			let prop = rapier_context.collider_parent(collider).unwrap_or(collider);
+			let direction = -normal;
+			let impact_speed = velocity.dot(direction);
+			if impact_speed <= 0.0 {continue;}
+			match hits.iter_mut().find(|(entity, _, _)| *entity == prop) {
+				Some(hit) => if impact_speed > hit.2 {
+					*hit = (prop, direction, impact_speed);
+				},
+				None => hits.push((prop, direction, impact_speed)),
+			}
+		}
+
+		for (prop, direction, impact_speed) in hits {
+			let Some(body) = rapier_context.entity2body().get(&prop).and_then(|handle| rapier_context.bodies.get(*handle)) else {continue;};
+			if !body.is_dynamic() {continue;}
+			// Momentum a collision between the two masses would exchange
+			let reduced_mass = player_mass * body.mass() / (player_mass + body.mass());
+			let smashed = impact_speed >= tuning.prop_smash_speed;
+			let push = tuning.prop_push_strength * reduced_mass * impact_speed * direction;
+			let mut impulse = push;
+			if smashed {
+				impulse = tuning.prop_smash_factor * push + tuning.prop_smash_lift * push.length() * movement.up;
+			}
+			// Only what goes beyond a plain push is left for marbles, smashes send props just as far either way
+			if pushed_by_physics {
+				impulse -= push;
+			}
+
+			let Ok(mut external_impulse) = impulses.get_mut(prop) else {continue;};
+			external_impulse.impulse += impulse;
+			hit_events.send(PropHit { player, prop, impulse, impact_speed, smashed });
+		}
+	}
+}
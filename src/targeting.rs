@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::input::PlayerInput;
+use crate::meme::MemeContainer;
+use crate::player::{MovementState, Player, PlayerCamera, PlayerMovement, find_eye_and_view, player_dash, player_move_input, update_movement_state};
+use crate::tuning::MovementTuning;
+
+pub struct TargetingPlugin;
+
+/// The `MemeContainer` a player has locked on to, momentum locks home in on it
+#[derive(Component, Clone, Copy, PartialEq, Debug, Default)]
+pub struct LockOn {
+	/// Container that is locked on to, if any
+	pub target: Option<Entity>,
+	/// How long the target has been out of sight, it gets dropped after `lock_on_sight_grace_time` [s]
+	pub time_out_of_sight: f32,
+}
+
+impl LockOn {
+	/// Whether there is a target and it can currently be seen
+	pub fn in_sight(&self) -> bool {
+		self.target.is_some() && self.time_out_of_sight <= 0.0
+	}
+}
+
+/// Sent when a player loses its target because it was out of sight for too long, stopped existing or was let go of
+#[derive(Event, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LockOnLost {
+	pub player: Entity,
+	pub target: Entity,
+}
+
+impl Plugin for TargetingPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.add_event::<LockOnLost>()
+			.add_systems(FixedUpdate, (
+				aim_lock_on,
+				track_lock_on,
+			).chain().after(update_movement_state).before(player_move_input))
+			.add_systems(FixedUpdate, home_towards_target.after(player_move_input).before(player_dash))
+		;
+	}
+}
+
+/// Whether nothing but the target itself is between the eye and the target
+fn can_see(rapier_context: &RapierContext, player: Entity, eye: Vec3, target: Entity, target_position: Vec3) -> bool {
+	let offset = target_position - eye;
+	let distance = offset.length();
+	if distance < 0.001 {return true;}
+	rapier_context.cast_ray(
+		eye,
+		offset / distance,
+		distance,
+		true,
+		QueryFilter::default().exclude_collider(player).exclude_sensors(),
+	).is_none_or(|(hit, _)| hit == target || rapier_context.collider_parent(hit) == Some(target))
+}
+
+/// Locks on to the container closest to the crosshair or lets go of the current target,
+/// moves the lock-on over to the next container in reach, in the order of the target list,
+/// or takes the container picked from the target list
+fn aim_lock_on(
+	mut players: Query<(Entity, &Transform, &PlayerInput, &mut LockOn), With<Player>>,
+	cameras: Query<(&Transform, &PlayerCamera, &Parent), Without<Player>>,
+	containers: Query<(Entity, &Transform), (With<MemeContainer>, Without<Player>)>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+	mut lost_events: EventWriter<LockOnLost>,
+) {
+	let max_angle = tuning.lock_on_aim_angle_degrees.to_radians();
+	for (player, transform, player_input, mut lock_on) in players.iter_mut() {
+		if let Some(selected) = player_input.lock_on_select {
+			if lock_on.target == Some(selected) {
+				*lock_on = LockOn::default();
+				lost_events.send(LockOnLost { player, target: selected });
+			} else if containers.contains(selected) {
+				*lock_on = LockOn { target: Some(selected), time_out_of_sight: 0.0 };
+			}
+			continue;
+		}
+		if !player_input.lock_on_pressed && !player_input.lock_on_cycle_pressed {continue;}
+		if let Some(target) = lock_on.target.filter(|_| player_input.lock_on_pressed) {
+			*lock_on = LockOn::default();
+			lost_events.send(LockOnLost { player, target });
+			continue;
+		}
+		let Some((eye, view_direction)) = find_eye_and_view(player, transform, cameras.iter()) else {continue;};
+
+		let mut candidates: Vec<(Entity, f32)> = containers.iter()
+			.filter_map(|(container, container_transform)| {
+				let offset = container_transform.translation - eye;
+				let angle = offset.angle_between(view_direction);
+				let in_reach = offset.length() <= tuning.lock_on_range && (player_input.lock_on_cycle_pressed || angle <= max_angle);
+				in_reach.then_some((container, container_transform.translation, angle))
+			})
+			.filter(|(container, position, _)| can_see(&rapier_context, player, eye, *container, *position))
+			.map(|(container, _, angle)| (container, angle))
+			.collect();
+
+		let new_target = if player_input.lock_on_cycle_pressed {
+			candidates.sort_by_key(|(container, _)| *container);
+			candidates.iter()
+				.find(|(container, _)| lock_on.target.is_some_and(|target| *container > target))
+				.or(candidates.first())
+				.map(|(container, _)| *container)
+		} else {
+			candidates.iter()
+				.min_by(|a, b| a.1.total_cmp(&b.1))
+				.map(|(container, _)| *container)
+		};
+		// Cycling with nothing else in reach keeps the current target
+		if new_target.is_none() && lock_on.target.is_some() {continue;}
+		lock_on.target = new_target;
+		lock_on.time_out_of_sight = 0.0;
+	}
+}
+
+/// Keeps track of whether targets can be seen, and drops those that have been out of sight for too long
+fn track_lock_on(
+	mut players: Query<(Entity, &Transform, &mut LockOn), With<Player>>,
+	cameras: Query<(&Transform, &PlayerCamera, &Parent), Without<Player>>,
+	containers: Query<&Transform, (With<MemeContainer>, Without<Player>)>,
+	rapier_context: Res<RapierContext>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+	mut lost_events: EventWriter<LockOnLost>,
+) {
+	let dt = time.delta_seconds();
+	for (player, transform, mut lock_on) in players.iter_mut() {
+		let Some(target) = lock_on.target else {continue;};
+		let in_sight = containers.get(target).is_ok_and(|target_transform| {
+			find_eye_and_view(player, transform, cameras.iter()).is_some_and(|(eye, _)| {
+				eye.distance(target_transform.translation) <= tuning.lock_on_range
+					&& can_see(&rapier_context, player, eye, target, target_transform.translation)
+			})
+		});
+
+		if in_sight {
+			lock_on.time_out_of_sight = 0.0;
+			continue;
+		}
+		lock_on.time_out_of_sight += dt;
+		if lock_on.time_out_of_sight > tuning.lock_on_sight_grace_time || !containers.contains(target) {
+			*lock_on = LockOn::default();
+			lost_events.send(LockOnLost { player, target });
+		}
+	}
+}
+
+/// Bends locked momentum towards the target, no faster than the homing rate allows
+fn home_towards_target(
+	mut players: Query<(&Transform, &LockOn, &mut PlayerMovement), With<Player>>,
+	containers: Query<&Transform, (With<MemeContainer>, Without<Player>)>,
+	tuning: Res<MovementTuning>,
+	time: Res<Time>,
+) {
+	let max_turn = tuning.lock_on_homing_rate * time.delta_seconds();
+	for (transform, lock_on, mut movement) in players.iter_mut() {
+		if movement.state != MovementState::Locked || !lock_on.in_sight() {continue;}
+		let Some(target_transform) = lock_on.target.and_then(|target| containers.get(target).ok()) else {continue;};
+		let to_target = target_transform.translation - transform.translation;
+		if to_target.length_squared() < 0.0001 {continue;}
+		movement.steer_towards(to_target, max_turn);
+	}
+}
@@ -0,0 +1,65 @@
+//! Headless apps running the simulation for tests, without rendering, UI or real time
+
+use std::time::Duration;
+
+use bevy::{prelude::*, time::TimeUpdateStrategy};
+use bevy_rapier3d::prelude::*;
+
+use crate::checkpoint::RespawnPoint;
+use crate::player::{Player, PlayerInterpolation};
+use crate::state::GameState;
+
+/// An app with everything that plays out in a level, fixed ticks only happen through `step`
+pub fn simulation_app() -> App {
+	let mut app = App::new();
+	app.add_plugins((
+		MinimalPlugins,
+		AssetPlugin::default(),
+		TransformPlugin,
+		HierarchyPlugin,
+		bevy::input::InputPlugin,
+		// The physics engine turns scenes into colliders
+		bevy::scene::ScenePlugin,
+	));
+	app.init_asset::<Mesh>();
+	// Real time never adds up to a fixed tick, so the same steps always produce the same results
+	app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::ZERO));
+	crate::add_simulation(&mut app);
+	app
+}
+
+/// Runs a single fixed tick
+pub fn step(app: &mut App) {
+	let timestep = app.world.resource::<Time<Fixed>>().timestep();
+	app.world.resource_mut::<Time<Fixed>>().advance_by(timestep);
+	// What the fixed loop does before running the schedule, systems read the fixed clock through `Time`
+	let fixed_time = app.world.resource::<Time<Fixed>>().as_generic();
+	*app.world.resource_mut::<Time>() = fixed_time;
+	app.world.run_schedule(FixedUpdate);
+}
+
+/// Enters the level, which spawns the player, and returns the player
+pub fn enter_level(app: &mut App) -> Entity {
+	app.world.resource_mut::<NextState<GameState>>().set(GameState::InLevel);
+	app.update();
+	app.world.query_filtered::<Entity, With<Player>>().single(&app.world)
+}
+
+/// Spawns an endless flat floor at zero height
+pub fn spawn_ground(app: &mut App) {
+	app.world.spawn((
+		TransformBundle::default(),
+		RigidBody::Fixed,
+		Collider::halfspace(Vec3::Y).unwrap(),
+	));
+}
+
+/// Teleports a player, without the rendered transform sliding over from where it was
+pub fn place_player(app: &mut App, player: Entity, position: Vec3) {
+	let transform = Transform::from_translation(position);
+	*app.world.get_mut::<Transform>(player).unwrap() = transform;
+	let mut interpolation = app.world.get_mut::<PlayerInterpolation>(player).unwrap();
+	interpolation.previous = transform;
+	interpolation.current = transform;
+	app.world.get_mut::<RespawnPoint>(player).unwrap().transform = transform;
+}
@@ -0,0 +1,371 @@
+use bevy::{
+	asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext},
+	prelude::*,
+	reflect::TypePath,
+	utils::BoxedFuture,
+};
+use serde::Deserialize;
+use thiserror::Error;
+
+pub struct TuningPlugin;
+
+/// Path of the tuning every level starts with, relative to the assets folder
+const BASE_MOVEMENT_TUNING_PATH: &str = "tuning/movement.tuning.ron";
+
+/// All the magic numbers that determine how player movement feels
+///
+/// Lives both as an asset (the files designers edit) and as a resource (the values currently in effect),
+/// fields missing from a file fall back to the values in `Default`
+#[derive(Asset, Resource, TypePath, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct MovementTuning {
+	/// Acceleration when looking straight up [m/s^2]
+	pub max_acceleration: f32,
+	/// Acceleration when looking straight ahead [m/s^2]
+	pub min_acceleration: f32,
+	/// Acceleration time the player is granted immediately upon starting to accelerate [s]
+	pub min_acceleration_time: f32,
+	/// Fraction of `time_accelerating` left after a second of the player not accelerating
+	pub acceleration_time_decay: f32,
+	/// Fraction of `desired_velocity` left after a second of the player not accelerating on the ground
+	pub ground_velocity_decay: f32,
+	/// Fraction of `desired_velocity` left after a second of the player not accelerating in the air
+	pub air_velocity_decay: f32,
+	/// Fraction of the acceleration and steering the player keeps while in the air
+	pub air_control: f32,
+	/// Quadratic drag coefficient in the air, deceleration is `air_drag * speed^2` [1/m]
+	pub air_drag: f32,
+	/// Fastest the player can fall [m/s]
+	pub terminal_velocity: f32,
+	/// How much of the downward velocity is kept when it gets redirected by a momentum lock
+	pub locked_downward_velocity_factor: f32,
+	/// What a momentum lock does to the velocity when it engages
+	pub momentum_lock_snap: MomentumLockSnap,
+	/// How long a momentum lock can be held before it runs out, `None` for no limit [s]
+	pub momentum_lock_time_budget: Option<f32>,
+	/// How quickly the time budget refills while not locked [s/s]
+	pub momentum_lock_budget_regen: f32,
+	/// Fraction of `desired_velocity` left after a second of being locked
+	pub momentum_lock_velocity_decay: f32,
+	/// How quickly a locked velocity turns towards the view direction [radian/s]
+	pub momentum_lock_steering: f32,
+	/// Upwards speed a jump starts with [m/s]
+	pub jump_speed: f32,
+	/// Factor the upwards speed is multiplied by when the jump key is released mid-jump
+	pub jump_cut_factor: f32,
+	/// How long after leaving the ground the player can still jump [s]
+	pub coyote_time: f32,
+	/// How long a jump press is remembered while it cannot be performed yet [s]
+	pub jump_buffer_time: f32,
+	/// Largest vertical component a surface normal can have for the surface to count as a wall
+	pub wall_max_normal_y: f32,
+	/// Horizontal speed needed to start running along a wall [m/s]
+	pub wall_run_min_speed: f32,
+	/// How long a wall run can last before the player has to touch ground again [s]
+	pub wall_run_max_time: f32,
+	/// Factor gravity is multiplied by while wall running
+	pub wall_run_gravity_factor: f32,
+	/// Speed the player is pushed into the wall with while wall running, keeps the contact alive [m/s]
+	pub wall_stick_speed: f32,
+	/// Speed a wall jump pushes the player away from the wall with [m/s]
+	pub wall_jump_push_speed: f32,
+	/// Speed into a wall needed to bounce off of it instead of sliding along it [m/s]
+	pub wall_bounce_min_speed: f32,
+	/// Fraction of the speed into a wall that is kept, reversed, when bouncing off of it
+	pub wall_restitution: f32,
+	/// Fraction of the remaining speed lost when bouncing off of a wall
+	pub wall_impact_speed_loss: f32,
+	/// Speed into a surface needed for the hit to be reported as an impact [m/s]
+	pub impact_event_min_speed: f32,
+	/// Steepest slope the player can stand on, anything steeper is slid down [degrees]
+	pub max_walkable_slope_degrees: f32,
+	/// Factor the downhill pull of gravity is multiplied by while on walkable slopes
+	pub slope_gravity_factor: f32,
+	/// How far below the player's feet ground is still looked for [m]
+	pub ground_probe_distance: f32,
+	/// Half of the length of the cylindrical part of the player's capsule while crouching [m]
+	pub crouch_half_height: f32,
+	/// Horizontal speed needed for a crouch on the ground to become a slide [m/s]
+	pub slide_min_speed: f32,
+	/// Fraction of `desired_velocity` left after a second of sliding
+	pub slide_velocity_decay: f32,
+	/// Furthest a grapple can attach from [m]
+	pub grapple_range: f32,
+	/// Shortest the grapple rope can be reeled in to [m]
+	pub grapple_min_length: f32,
+	/// Speed the grapple rope is reeled in and out with [m/s]
+	pub grapple_reel_speed: f32,
+	/// Time after detaching before the grapple can be fired again [s]
+	pub grapple_cooldown: f32,
+	/// Speed the player is pulled back with per meter the rope is stretched [1/s]
+	pub grapple_stiffness: f32,
+	/// Fraction of the momentum taken from the player by the rope that gets transferred to dynamic bodies
+	pub grapple_pull_strength: f32,
+	/// Speed a dash adds in the view direction [m/s]
+	pub dash_speed: f32,
+	/// Energy spent by a single dash
+	pub dash_energy_cost: f32,
+	/// Most energy that can be stored for dashing
+	pub dash_max_energy: f32,
+	/// Energy regained per second of accelerating [1/s]
+	pub dash_energy_regen: f32,
+	/// Shortest time between two dashes [s]
+	pub dash_cooldown: f32,
+	/// How the player gets moved around, players are rebuilt whenever this changes
+	pub player_controller: PlayerControllerKind,
+	/// Fraction of the speed into a surface a marble keeps, reversed, when bouncing off of it
+	pub marble_restitution: f32,
+	/// Friction coefficient between a marble and what it rolls on, limits how hard it can speed up or brake
+	pub marble_friction: f32,
+	/// Rolling resistance coefficient of a marble on the ground, deceleration is `marble_rolling_resistance` times the strength of gravity
+	pub marble_rolling_resistance: f32,
+	/// Factor the momentum a player hands over to props it runs into is multiplied by, 2 for a perfectly elastic hit
+	pub prop_push_strength: f32,
+	/// Speed into a prop needed to smash it and send it flying [m/s]
+	pub prop_smash_speed: f32,
+	/// Factor the push is multiplied by when smashing a prop
+	pub prop_smash_factor: f32,
+	/// Upwards impulse added when smashing a prop, as a fraction of the push
+	pub prop_smash_lift: f32,
+	/// How much damage players can take, what deals it and how they come back from being destroyed
+	pub integrity: IntegrityTuning,
+	/// Furthest a container can be locked on to and stay locked on to [m]
+	pub lock_on_range: f32,
+	/// Largest angle between the view and a container for aiming to lock on to it [degrees]
+	pub lock_on_aim_angle_degrees: f32,
+	/// How long a target can be out of sight before the lock-on lets go of it [s]
+	pub lock_on_sight_grace_time: f32,
+	/// How quickly a momentum lock turns towards the lock-on target, zero to not home in at all [radian/s]
+	pub lock_on_homing_rate: f32,
+	/// How quickly a player's orientation turns to match a new up direction, see `GravityZone` and `SurfaceGravity` [1/s]
+	pub up_alignment_rate: f32,
+}
+
+impl Default for MovementTuning {
+	fn default() -> Self {
+		Self {
+			max_acceleration: 15.0,
+			min_acceleration: 3.0,
+			min_acceleration_time: 0.5,
+			acceleration_time_decay: 0.0012,
+			ground_velocity_decay: 0.0375,
+			air_velocity_decay: 0.5256,
+			air_control: 0.3,
+			air_drag: 0.002,
+			terminal_velocity: 55.0,
+			locked_downward_velocity_factor: 0.1,
+			momentum_lock_snap: MomentumLockSnap::View,
+			momentum_lock_time_budget: None,
+			momentum_lock_budget_regen: 0.5,
+			momentum_lock_velocity_decay: 1.0,
+			momentum_lock_steering: 0.0,
+			jump_speed: 6.0,
+			jump_cut_factor: 0.5,
+			coyote_time: 0.15,
+			jump_buffer_time: 0.15,
+			wall_max_normal_y: 0.3,
+			wall_run_min_speed: 8.0,
+			wall_run_max_time: 1.5,
+			wall_run_gravity_factor: 0.25,
+			wall_stick_speed: 1.0,
+			wall_jump_push_speed: 6.0,
+			wall_bounce_min_speed: 6.0,
+			wall_restitution: 0.3,
+			wall_impact_speed_loss: 0.1,
+			impact_event_min_speed: 2.0,
+			max_walkable_slope_degrees: 50.0,
+			slope_gravity_factor: 1.0,
+			ground_probe_distance: 0.3,
+			crouch_half_height: 0.4,
+			slide_min_speed: 6.0,
+			slide_velocity_decay: 0.7256,
+			grapple_range: 40.0,
+			grapple_min_length: 2.0,
+			grapple_reel_speed: 8.0,
+			grapple_cooldown: 1.0,
+			grapple_stiffness: 5.0,
+			grapple_pull_strength: 0.5,
+			dash_speed: 12.0,
+			dash_energy_cost: 40.0,
+			dash_max_energy: 100.0,
+			dash_energy_regen: 20.0,
+			dash_cooldown: 0.5,
+			player_controller: PlayerControllerKind::Capsule,
+			marble_restitution: 0.5,
+			marble_friction: 1.0,
+			marble_rolling_resistance: 0.02,
+			prop_push_strength: 1.0,
+			prop_smash_speed: 15.0,
+			prop_smash_factor: 3.0,
+			prop_smash_lift: 0.5,
+			integrity: IntegrityTuning::default(),
+			lock_on_range: 60.0,
+			lock_on_aim_angle_degrees: 10.0,
+			lock_on_sight_grace_time: 0.5,
+			lock_on_homing_rate: 1.5,
+			up_alignment_rate: 8.0,
+		}
+	}
+}
+
+/// The part of `MovementTuning` about player integrity, its own section in tuning files
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(default)]
+pub struct IntegrityTuning {
+	/// Integrity a player starts with and can regenerate up to
+	pub max_integrity: f32,
+	/// Integrity regenerated per second once regeneration kicks in [1/s]
+	pub integrity_regen: f32,
+	/// How long after taking damage regeneration kicks in [s]
+	pub integrity_regen_delay: f32,
+	/// Speed into a surface above which hitting it deals damage [m/s]
+	pub impact_damage_min_speed: f32,
+	/// Damage dealt per unit of impact speed above `impact_damage_min_speed` [1/(m/s)]
+	pub impact_damage_per_speed: f32,
+	/// Furthest an infohazard can be seen from [m]
+	pub memetic_exposure_range: f32,
+	/// Largest angle between the view and an infohazard for it to count as looked at [degrees]
+	pub memetic_exposure_angle_degrees: f32,
+	/// Damage dealt per second of looking at an infohazard [1/s]
+	pub memetic_damage_per_second: f32,
+	/// How long a destroyed player waits before respawning [s]
+	pub respawn_delay: f32,
+}
+
+impl Default for IntegrityTuning {
+	fn default() -> Self {
+		Self {
+			max_integrity: 100.0,
+			integrity_regen: 10.0,
+			integrity_regen_delay: 3.0,
+			impact_damage_min_speed: 15.0,
+			impact_damage_per_speed: 5.0,
+			memetic_exposure_range: 20.0,
+			memetic_exposure_angle_degrees: 20.0,
+			memetic_damage_per_second: 15.0,
+			respawn_delay: 2.0,
+		}
+	}
+}
+
+/// What happens to the velocity at the moment a momentum lock engages
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MomentumLockSnap {
+	/// Redirect all of the speed to where the player is looking
+	#[default]
+	View,
+	/// Keep the velocity exactly as it was
+	KeepDirection,
+}
+
+/// Which kind of body the player is, levels pick one through their tuning
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PlayerControllerKind {
+	/// A capsule moved by a kinematic character controller
+	#[default]
+	Capsule,
+	/// A dynamic ball pushed around by forces, see `Marble`
+	Marble,
+}
+
+/// Where the active `MovementTuning` resource is taken from
+#[derive(Resource, Clone, PartialEq, Eq, Debug, Default)]
+pub struct MovementTuningSource {
+	/// Tuning shared by all levels
+	pub base: Handle<MovementTuning>,
+	/// Tuning for the current level, replaces `base` entirely while set and loaded, follows the `LevelTuning` of the level
+	pub level_override: Option<Handle<MovementTuning>>,
+}
+
+/// Put on the root entity of a level to play it with its own tuning instead of the base one
+#[derive(Component, Clone, PartialEq, Eq, Debug)]
+pub struct LevelTuning(pub Handle<MovementTuning>);
+
+#[derive(Default)]
+struct MovementTuningLoader;
+
+#[derive(Debug, Error)]
+enum MovementTuningLoaderError {
+	#[error("Could not read movement tuning: {0}")]
+	Io(#[from] std::io::Error),
+	#[error("Could not parse movement tuning: {0}")]
+	Ron(#[from] ron::error::SpannedError),
+}
+
+impl AssetLoader for MovementTuningLoader {
+	type Asset = MovementTuning;
+	type Settings = ();
+	type Error = MovementTuningLoaderError;
+
+	fn load<'a>(
+		&'a self,
+		reader: &'a mut Reader,
+		_settings: &'a (),
+		_load_context: &'a mut LoadContext,
+	) -> BoxedFuture<'a, Result<Self::Asset, Self::Error>> {
+		Box::pin(async move {
+			let mut bytes = Vec::new();
+			reader.read_to_end(&mut bytes).await?;
+			Ok(ron::de::from_bytes::<MovementTuning>(&bytes)?)
+		})
+	}
+
+	fn extensions(&self) -> &[&str] {
+		&["tuning.ron"]
+	}
+}
+
+impl Plugin for TuningPlugin {
+	fn build(&self, app: &mut App) {
+		app
+			.init_asset::<MovementTuning>()
+			.init_asset_loader::<MovementTuningLoader>()
+			.init_resource::<MovementTuning>()
+			.init_resource::<MovementTuningSource>()
+			.add_systems(Startup, load_base_movement_tuning)
+			.add_systems(PreUpdate, (select_level_tuning, apply_movement_tuning).chain())
+		;
+	}
+}
+
+fn load_base_movement_tuning(
+	assets: Res<AssetServer>,
+	mut source: ResMut<MovementTuningSource>,
+) {
+	source.base = assets.load(BASE_MOVEMENT_TUNING_PATH);
+}
+
+/// Points the level override at the `LevelTuning` of the current level, or clears it once the level is gone
+fn select_level_tuning(
+	levels: Query<Ref<LevelTuning>>,
+	mut removed_levels: RemovedComponents<LevelTuning>,
+	mut source: ResMut<MovementTuningSource>,
+) {
+	let level_removed = removed_levels.read().count() > 0;
+	let level_changed = levels.iter().any(|level| level.is_changed());
+	if !level_removed && !level_changed {return;}
+
+	let level_override = levels.iter().next().map(|level| level.0.clone());
+	if source.level_override != level_override {
+		source.level_override = level_override;
+	}
+}
+
+/// Copies the selected tuning asset into the `MovementTuning` resource whenever the selection or the asset itself changes (hot reload)
+fn apply_movement_tuning(
+	mut asset_events: EventReader<AssetEvent<MovementTuning>>,
+	tunings: Res<Assets<MovementTuning>>,
+	source: Res<MovementTuningSource>,
+	mut tuning: ResMut<MovementTuning>,
+) {
+	let assets_changed = asset_events.read().count() > 0;
+	if !assets_changed && !source.is_changed() {return;}
+
+	let selected = source.level_override.as_ref()
+		.and_then(|handle| tunings.get(handle))
+		.or_else(|| tunings.get(&source.base));
+	let new_tuning = selected.copied().unwrap_or_default();
+	if *tuning != new_tuning {
+		*tuning = new_tuning;
+	}
+}
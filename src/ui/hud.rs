@@ -10,16 +10,63 @@ use crate::bevy_simple_text_input::TextInput;
 
 use bevy::app::{Plugin, App};
 
-use crate::input::InputMap;
+use crate::camera_rig::{split_screen_rect, split_screen_slot};
+use crate::input::{InputMap, InputSource, CursorGrab, LocalInput, PendingPlayerInput, PlayerInput};
 
 pub struct HUDPlugin;
 
-#[derive(Resource, Default)]
-/// Resource containing all data that the hud wants to display
+#[derive(Component)]
+/// All data a HUD wants to display, sits on the root node of the HUD of every local player
 struct HUDData {
+	/// Player the HUD shows the state of
+	player: Entity,
 	terminal_open: bool,
 	player_locked_momentum: bool,
-	meme_data: Vec<String>,
+	/// Time left in the momentum lock time budget, if there is a budget
+	momentum_lock_time_left: Option<f32>,
+	grapple_attached: bool,
+	/// Fraction of the maximum dash energy the player has
+	dash_energy_fraction: f32,
+	/// Fraction of the maximum integrity the player has
+	integrity_fraction: f32,
+	/// Whether the player is waiting to respawn
+	destroyed: bool,
+	/// Every container that can be targeted, with its description
+	meme_data: Vec<(Entity, String)>,
+	/// Container the player is locked on to
+	lock_on_target: Option<Entity>,
+	reticle: ReticleState,
+}
+
+impl HUDData {
+	fn new(player: Entity) -> Self {
+		Self {
+			player,
+			terminal_open: false,
+			player_locked_momentum: false,
+			momentum_lock_time_left: None,
+			grapple_attached: false,
+			dash_energy_fraction: 0.0,
+			integrity_fraction: 1.0,
+			destroyed: false,
+			meme_data: Vec::new(),
+			lock_on_target: None,
+			reticle: ReticleState::NoTarget,
+		}
+	}
+}
+
+/// What the lock-on reticle is showing
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+enum ReticleState {
+	#[default]
+	NoTarget,
+	/// Locked on, but not homing in
+	Tracking,
+	/// Locked on and homing in with a momentum lock
+	Homing,
+	/// Locked on, but the target can't be seen and will soon be dropped
+	SightLost,
 }
 
 #[derive(Component)]
@@ -29,41 +76,157 @@ struct TerminalTextInput;
 #[derive(Component)]
 struct MomentumLockIndicatorText;
 #[derive(Component)]
-struct TargetListText;
+struct DashEnergyBarFill;
+#[derive(Component)]
+struct IntegrityBarFill;
+#[derive(Component)]
+struct DestroyedScreen;
+#[derive(Component)]
+struct GrappleIndicatorText;
+#[derive(Component)]
+struct TargetList;
+/// Clickable entry of the target list, locks on to the container
+#[derive(Component)]
+struct TargetListEntry(Entity);
+#[derive(Component)]
+struct LockOnReticle;
 
 impl Plugin for HUDPlugin {
 	fn build(&self, app: &mut App) {
 		app
-		.init_resource::<HUDData>()
-		.add_systems(Startup, setup)
 		.add_systems(Update, mouse_scroll)
 		.add_systems(Update, (
+			update_huds,
 			(
 				update_hud_data_from_player_inputs,
 				update_hud_data_from_player_data,
+				update_hud_data_from_grapple,
 				update_hud_data_from_memetics,
+				update_hud_data_from_lock_on,
 			), (
+				layout_huds,
+				select_target_from_list,
 				update_hud_terminal,
+				update_cursor_release,
 				update_hud_momentum_lock,
+				update_hud_dash_energy,
+				update_hud_integrity,
+				update_hud_destroyed_screen,
+				update_hud_grapple,
 				update_target_list,
+				update_lock_on_reticle,
 			),
 		).chain())
 		;
 	}
 }
 
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
+/// Gives every local player a HUD of its own, and takes it away again along with the player
+fn update_huds(
+	mut commands: Commands,
+	players: Query<Entity, (With<crate::player::Player>, With<LocalInput>)>,
+	huds: Query<(Entity, &HUDData)>,
+	asset_server: Res<AssetServer>,
+) {
+	for (hud, hud_data) in huds.iter() {
+		if !players.contains(hud_data.player) {
+			commands.entity(hud).despawn_recursive();
+		}
+	}
+	for player in players.iter() {
+		if huds.iter().any(|(_, hud_data)| hud_data.player == player) {continue;}
+		spawn_hud(&mut commands, &asset_server, player);
+	}
+}
+
+/// Lays every HUD out over the part of the window its player's view gets
+fn layout_huds(
+	mut huds: Query<(&HUDData, &mut Style)>,
+	players: Query<Entity, (With<crate::player::Player>, With<LocalInput>)>,
+) {
+	for (hud_data, mut style) in huds.iter_mut() {
+		let Some((index, count)) = split_screen_slot(hud_data.player, players.iter()) else {continue;};
+		let rect = split_screen_rect(index, count);
+		let left = Val::Percent(100.0 * rect.min.x);
+		let top = Val::Percent(100.0 * rect.min.y);
+		let width = Val::Percent(100.0 * rect.width());
+		let height = Val::Percent(100.0 * rect.height());
+		if (style.left, style.top, style.width, style.height) != (left, top, width, height) {
+			style.left = left;
+			style.top = top;
+			style.width = width;
+			style.height = height;
+		}
+	}
+}
+
+/// The data of the HUD a node is part of
+fn hud_of<'a>(node: Entity, parents: &Query<&Parent>, huds: &'a Query<&HUDData>) -> Option<&'a HUDData> {
+	huds.get(node).ok().or_else(|| parents.iter_ancestors(node).find_map(|ancestor| huds.get(ancestor).ok()))
+}
+
+/// Spawns a labelled bar, its fill is marked with `marker` and starts out empty
+fn spawn_meter(parent: &mut ChildBuilder, label: &str, label_style: &TextStyle, color: Color, marker: impl Component) {
+	parent
+		.spawn(NodeBundle {
+			style: Style {
+				flex_direction: FlexDirection::Row,
+				align_items: AlignItems::Center,
+				margin: UiRect::all(Val::Px(5.)),
+				..default()
+			},
+			..default()
+		})
+		.with_children(|parent| {
+			parent.spawn((
+				TextBundle::from_section(label, label_style.clone()),
+				Label,
+			));
+			parent
+				.spawn(NodeBundle {
+					style: Style {
+						flex_grow: 1.,
+						height: Val::Px(20.),
+						border: UiRect::all(Val::Px(2.)),
+						..default()
+					},
+					border_color: Color::GOLD.into(),
+					background_color: Color::BLACK.into(),
+					..default()
+				})
+				.with_children(|parent| {
+					parent.spawn((
+						NodeBundle {
+							style: Style {
+								width: Val::Percent(0.),
+								height: Val::Percent(100.),
+								..default()
+							},
+							background_color: color.into(),
+							..default()
+						},
+						marker,
+					));
+				});
+		});
+}
+
+fn spawn_hud(commands: &mut Commands, asset_server: &AssetServer, player: Entity) {
 	// root node
 	commands
-		.spawn(NodeBundle {
+		.spawn((NodeBundle {
 			style: Style {
+				// Placed over the player's part of the window by `layout_huds`
+				position_type: PositionType::Absolute,
 				width: Val::Percent(100.0),
 				height: Val::Percent(100.0),
 				justify_content: JustifyContent::SpaceBetween,
 				..default()
 			},
 			..default()
-		})
+		},
+			HUDData::new(player),
+		))
 		.with_children(|parent| {
 			// left vertical fill (border)
 			parent
@@ -99,7 +262,7 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
 							parent.spawn((
 								TextBundle::from_sections([
 									TextSection::new("MOMENTUM LOCK: ", indicator_text_style.clone()),
-									TextSection::from_style(indicator_text_style), // VALUE GOES HERE
+									TextSection::from_style(indicator_text_style.clone()), // VALUE GOES HERE
 								]).with_style(Style {
 									margin: UiRect::all(Val::Px(5.)),
 									..default()
@@ -107,6 +270,20 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
 								Label, // For accessibility
 								MomentumLockIndicatorText,
 							));
+							spawn_meter(parent, "INTEGRITY: ", &indicator_text_style, Color::RED, IntegrityBarFill);
+							spawn_meter(parent, "DASH: ", &indicator_text_style, Color::CYAN, DashEnergyBarFill);
+							// Grapple indicator
+							parent.spawn((
+								TextBundle::from_sections([
+									TextSection::new("GRAPPLE: ", indicator_text_style.clone()),
+									TextSection::from_style(indicator_text_style), // VALUE GOES HERE
+								]).with_style(Style {
+									margin: UiRect::all(Val::Px(5.)),
+									..default()
+								}),
+								Label,
+								GrappleIndicatorText,
+							));
 						});
 				});
 			// Middle command terminal
@@ -215,26 +392,51 @@ fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
 										..default()
 									},
 									ScrollingList::default(),
+									TargetList,
 									AccessibilityNode(NodeBuilder::new(Role::List)),
-								))
-								.with_children(|parent| {
-									let font = asset_server.load("fonts/ComicShannsV2.ttf");
-									let style = TextStyle {
-										font,
-										font_size: 20.,
-										..default()
-									};
-									parent.spawn((
-										TextBundle::from_section(
-											format!("THIS PLACEHOLDER IS COMPLETELY INTENTIONAL\n"), style
-										),
-										TargetListText,
-										Label,
-										AccessibilityNode(NodeBuilder::new(Role::ListItem)),
-									));
-								});
+								));
 						});
 				});
+			// Lock-on reticle, placed over the target
+			parent.spawn((
+				TextBundle::from_section("[ ]", TextStyle {
+					font: asset_server.load("fonts/ComicMono.ttf"),
+					font_size: 40.,
+					..default()
+				}).with_style(Style {
+					position_type: PositionType::Absolute,
+					..default()
+				}),
+				Label,
+				LockOnReticle,
+			));
+			// Shown while waiting to respawn
+			parent
+				.spawn((NodeBundle {
+					style: Style {
+						position_type: PositionType::Absolute,
+						width: Val::Percent(100.),
+						height: Val::Percent(100.),
+						justify_content: JustifyContent::Center,
+						align_items: AlignItems::Center,
+						..default()
+					},
+					visibility: Visibility::Hidden,
+					background_color: Color::rgba(0.4, 0.0, 0.0, 0.4).into(),
+					..default()
+				},
+					DestroyedScreen,
+				))
+				.with_children(|parent| {
+					parent.spawn((
+						TextBundle::from_section("INTEGRITY LOST", TextStyle {
+							font: asset_server.load("fonts/ComicShannsV2.ttf"),
+							font_size: 60.,
+							color: Color::RED,
+						}),
+						Label,
+					));
+				});
 		});
 }
 
@@ -270,83 +472,264 @@ fn mouse_scroll(
 fn update_hud_data_from_player_inputs(
 	inputs: Res<Input<KeyCode>>,
 	input_map: Res<InputMap>,
-	mut hud_data: ResMut<HUDData>
+	players: Query<&LocalInput>,
+	mut huds: Query<&mut HUDData>
 ) {
-	if inputs.just_pressed(input_map.terminal_key) {
-		hud_data.terminal_open = !hud_data.terminal_open;
+	if !inputs.just_pressed(input_map.terminal_key) {return;}
+	// Only whoever plays on the keyboard can type into the terminal
+	for mut hud_data in huds.iter_mut() {
+		if players.get(hud_data.player).is_ok_and(|local_input| local_input.source == InputSource::KeyboardMouse) {
+			hud_data.terminal_open = !hud_data.terminal_open;
+		}
 	}
 }
 
 fn update_hud_data_from_memetics(
-	memetics: Query<&crate::meme::MemeContainer>,
-	mut hud_data: ResMut<HUDData>
+	memetics: Query<(Entity, &crate::meme::MemeContainer)>,
+	mut huds: Query<&mut HUDData>
 ) {
-	hud_data.meme_data.clear();
+	for mut hud_data in huds.iter_mut() {
+		hud_data.meme_data.clear();
+		for (container, meme) in memetics.iter() {
+			hud_data.meme_data.push((container, format!("{:?} {:?}", meme.container_type, meme.contains_infohazard)))
+		}
+		// The same order cycling the lock-on goes in
+		hud_data.meme_data.sort_by_key(|(container, _)| *container);
+	}
+}
 
-	for meme in memetics.iter() {
-		hud_data.meme_data.push(format!("{:?} {:?}", meme.container_type, meme.contains_infohazard))
+fn update_hud_data_from_lock_on(
+	player: Query<(&crate::targeting::LockOn, &crate::player::PlayerMovement)>,
+	mut huds: Query<&mut HUDData>
+) {
+	for mut hud_data in huds.iter_mut() {
+		let lock_on = player.get(hud_data.player).ok();
+		hud_data.lock_on_target = lock_on.and_then(|(lock_on, _)| lock_on.target);
+		hud_data.reticle = match lock_on {
+			None | Some((crate::targeting::LockOn { target: None, .. }, _)) => ReticleState::NoTarget,
+			Some((lock_on, _)) if !lock_on.in_sight() => ReticleState::SightLost,
+			Some((_, movement)) if movement.state == crate::player::MovementState::Locked => ReticleState::Homing,
+			Some(_) => ReticleState::Tracking,
+		};
 	}
 }
 
 fn update_hud_data_from_player_data(
-	player: Query<&crate::player::PlayerMovement>,
-	mut hud_data: ResMut<HUDData>
+	player: Query<(&crate::player::PlayerMovement, &crate::player::PlayerDash, Option<&crate::integrity::PlayerIntegrity>)>,
+	tuning: Res<crate::tuning::MovementTuning>,
+	mut huds: Query<&mut HUDData>
 ) {
-	let Some(player) = ({
-		let mut player_candidate = None;
-		for a in player.iter() {
-			player_candidate = Some(a);
-			break;
-		}
-		player_candidate
-	}) else {return;};
+	for mut hud_data in huds.iter_mut() {
+		let Ok((player, dash, integrity)) = player.get(hud_data.player) else {continue;};
 
-	hud_data.player_locked_momentum = player.locked_velocity;
+		hud_data.player_locked_momentum = player.state == crate::player::MovementState::Locked;
+		hud_data.momentum_lock_time_left = tuning.momentum_lock_time_budget.map(|budget| f32::max(budget - player.lock_time_used, 0.0));
+		hud_data.dash_energy_fraction = if tuning.dash_max_energy > 0.0 {dash.energy / tuning.dash_max_energy} else {0.0};
+		hud_data.integrity_fraction = match integrity {
+			Some(integrity) if tuning.integrity.max_integrity > 0.0 => integrity.integrity / tuning.integrity.max_integrity,
+			_ => 1.0,
+		};
+		hud_data.destroyed = integrity.is_some_and(|integrity| integrity.respawn_timer.is_some());
+	}
+}
+
+/// Reads the grapple itself rather than its events, an attach and a detach within one frame can't be told apart otherwise
+fn update_hud_data_from_grapple(
+	player: Query<&crate::grapple::Grapple>,
+	mut huds: Query<&mut HUDData>
+) {
+	for mut hud_data in huds.iter_mut() {
+		hud_data.grapple_attached = player.get(hud_data.player).is_ok_and(|grapple| grapple.attachment.is_some());
+	}
 }
 
 fn update_hud_terminal(
-	mut terminal_window: Query<&mut Visibility, With<TerminalWindow>>,
-	mut terminal_input: Query<&mut TextInput, With<TerminalTextInput>>,
-	hud_data: Res<HUDData>
+	mut terminal_window: Query<(Entity, &mut Visibility), With<TerminalWindow>>,
+	mut terminal_input: Query<(Entity, &mut TextInput), With<TerminalTextInput>>,
+	parents: Query<&Parent>,
+	huds: Query<&HUDData>
 ) {
-	for mut window_visibility in terminal_window.iter_mut() {
+	for (window, mut window_visibility) in terminal_window.iter_mut() {
+		let Some(hud_data) = hud_of(window, &parents, &huds) else {continue;};
 		*window_visibility = if hud_data.terminal_open {Visibility::Visible} else {Visibility::Hidden};
 	}
-	for mut text_input in terminal_input.iter_mut() {
+	for (input, mut text_input) in terminal_input.iter_mut() {
+		let Some(hud_data) = hud_of(input, &parents, &huds) else {continue;};
 		text_input.inactive = !hud_data.terminal_open;
 	}
 }
 
+fn update_cursor_release(
+	mut cursor_grab: ResMut<CursorGrab>,
+	huds: Query<&HUDData>
+) {
+	// Typing into the terminal needs the cursor back
+	cursor_grab.released_by_ui = huds.iter().any(|hud_data| hud_data.terminal_open);
+}
+
 fn update_hud_momentum_lock(
-	mut indicator: Query<&mut Text, With<MomentumLockIndicatorText>>,
-	hud_data: Res<HUDData>
+	mut indicator: Query<(Entity, &mut Text), With<MomentumLockIndicatorText>>,
+	parents: Query<&Parent>,
+	huds: Query<&HUDData>
 ) {
-	for mut indicator in indicator.iter_mut() {
+	for (entity, mut indicator) in indicator.iter_mut() {
+		let Some(hud_data) = hud_of(entity, &parents, &huds) else {continue;};
 		// I trust my ass to not fuck this up in the hud creation
-		indicator.sections[1].value = (if hud_data.player_locked_momentum {"ENABLED"} else {"DISABLED"}).into();
+		let state = if hud_data.player_locked_momentum {"ENABLED"} else {"DISABLED"};
+		indicator.sections[1].value = match hud_data.momentum_lock_time_left {
+			Some(time_left) => format!("{state} ({time_left:.1}s)"),
+			None => state.into(),
+		};
 		indicator.sections[1].style.color = if hud_data.player_locked_momentum {Color::GREEN} else {Color::PINK};
 	}
 }
 
+fn update_hud_dash_energy(
+	mut bar: Query<(Entity, &mut Style), With<DashEnergyBarFill>>,
+	parents: Query<&Parent>,
+	huds: Query<&HUDData>
+) {
+	for (entity, mut bar) in bar.iter_mut() {
+		let Some(hud_data) = hud_of(entity, &parents, &huds) else {continue;};
+		bar.width = Val::Percent(100. * hud_data.dash_energy_fraction.clamp(0., 1.));
+	}
+}
+
+fn update_hud_integrity(
+	mut bar: Query<(Entity, &mut Style), With<IntegrityBarFill>>,
+	parents: Query<&Parent>,
+	huds: Query<&HUDData>
+) {
+	for (entity, mut bar) in bar.iter_mut() {
+		let Some(hud_data) = hud_of(entity, &parents, &huds) else {continue;};
+		bar.width = Val::Percent(100. * hud_data.integrity_fraction.clamp(0., 1.));
+	}
+}
+
+/// Shows the destroyed screen over the HUD of every player waiting to respawn
+fn update_hud_destroyed_screen(
+	mut screen: Query<(Entity, &mut Visibility), With<DestroyedScreen>>,
+	parents: Query<&Parent>,
+	huds: Query<&HUDData>
+) {
+	for (entity, mut visibility) in screen.iter_mut() {
+		let Some(hud_data) = hud_of(entity, &parents, &huds) else {continue;};
+		*visibility = if hud_data.destroyed {Visibility::Visible} else {Visibility::Hidden};
+	}
+}
+
+fn update_hud_grapple(
+	mut indicator: Query<(Entity, &mut Text), With<GrappleIndicatorText>>,
+	parents: Query<&Parent>,
+	huds: Query<&HUDData>
+) {
+	for (entity, mut indicator) in indicator.iter_mut() {
+		let Some(hud_data) = hud_of(entity, &parents, &huds) else {continue;};
+		indicator.sections[1].value = (if hud_data.grapple_attached {"ATTACHED"} else {"DETACHED"}).into();
+		indicator.sections[1].style.color = if hud_data.grapple_attached {Color::GREEN} else {Color::PINK};
+	}
+}
+
+/// Rebuilds the target list whenever the set of containers changes, and highlights the current target
 fn update_target_list(
-	mut list_query: Query<&mut Text, With<TargetListText>>,
-	hud_data: Res<HUDData>,
+	mut commands: Commands,
+	lists: Query<(Entity, Option<&Children>), With<TargetList>>,
+	mut entries: Query<(&TargetListEntry, &mut BackgroundColor)>,
+	parents: Query<&Parent>,
+	huds: Query<&HUDData>,
 	asset_server: Res<AssetServer>
 ) {
-	let font = asset_server.load("fonts/ComicShannsV2.ttf");
-	let default_style = TextStyle {
-		font,
-		font_size: 20.,
-		..default()
-	};
-	for mut list_text in list_query.iter_mut() {
-		list_text.sections.clear();
-		list_text.sections.extend(hud_data.meme_data.iter().map(
-			|text| {
-				TextSection {
-					value: format!("{text}\n"), style: default_style.clone()
+	for (list, children) in lists.iter() {
+		let Some(hud_data) = hud_of(list, &parents, &huds) else {continue;};
+		let children: Vec<Entity> = children.map(|children| children.to_vec()).unwrap_or_default();
+		let shown: Vec<Entity> = children.iter()
+			.filter_map(|child| entries.get(*child).ok().map(|(entry, _)| entry.0))
+			.collect();
+		let wanted: Vec<Entity> = hud_data.meme_data.iter().map(|(container, _)| *container).collect();
+
+		if shown != wanted {
+			let font = asset_server.load("fonts/ComicShannsV2.ttf");
+			let default_style = TextStyle {
+				font,
+				font_size: 20.,
+				..default()
+			};
+			commands.entity(list).despawn_descendants().with_children(|parent| {
+				for (container, text) in hud_data.meme_data.iter() {
+					parent
+						.spawn((
+							ButtonBundle {
+								style: Style {
+									padding: UiRect::all(Val::Px(2.)),
+									..default()
+								},
+								background_color: Color::NONE.into(),
+								..default()
+							},
+							TargetListEntry(*container),
+							AccessibilityNode(NodeBuilder::new(Role::ListItem)),
+						))
+						.with_children(|parent| {
+							parent.spawn((
+								TextBundle::from_section(text.clone(), default_style.clone()),
+								Label,
+							));
+						});
 				}
-			}
-		));
+			});
+			continue;
+		}
+
+		for child in children.iter() {
+			let Ok((entry, mut background)) = entries.get_mut(*child) else {continue;};
+			*background = if hud_data.lock_on_target == Some(entry.0) {Color::rgb(0.3, 0.1, 0.4)} else {Color::NONE}.into();
+		}
+	}
+}
+
+/// Clicking an entry of the target list locks on to it, clicking the current target lets go of it
+fn select_target_from_list(
+	entries: Query<(Entity, &Interaction, &TargetListEntry), Changed<Interaction>>,
+	players: Query<&LocalInput>,
+	parents: Query<&Parent>,
+	huds: Query<&HUDData>,
+	mut pending: ResMut<PendingPlayerInput>,
+) {
+	for (entity, interaction, entry) in entries.iter() {
+		if *interaction != Interaction::Pressed {continue;}
+		let Some(hud_data) = hud_of(entity, &parents, &huds) else {continue;};
+		let Ok(local_input) = players.get(hud_data.player) else {continue;};
+		// Goes through the player's input like any other action, so replays pick the same target
+		pending.accumulate(local_input.source, &PlayerInput {lock_on_select: Some(entry.0), ..Default::default()});
+	}
+}
+
+/// Keeps the reticle over the target on screen, colored by what the lock-on is doing
+fn update_lock_on_reticle(
+	mut reticle: Query<(Entity, &mut Style, &mut Text, &mut Visibility, &Node), With<LockOnReticle>>,
+	cameras: Query<(&Camera, &GlobalTransform, &crate::camera_rig::CameraRig)>,
+	targets: Query<&GlobalTransform>,
+	parents: Query<&Parent>,
+	huds: Query<&HUDData>
+) {
+	for (entity, mut style, mut text, mut visibility, node) in reticle.iter_mut() {
+		let Some(hud_data) = hud_of(entity, &parents, &huds) else {continue;};
+		let screen_position = hud_data.lock_on_target
+			.and_then(|target| targets.get(target).ok())
+			.zip(cameras.iter().find(|(_, _, rig)| rig.player == hud_data.player))
+			.and_then(|(target, (camera, camera_transform, _))| camera.world_to_viewport(camera_transform, target.translation()));
+
+		let Some(position) = screen_position.filter(|_| hud_data.reticle != ReticleState::NoTarget) else {
+			*visibility = Visibility::Hidden;
+			continue;
+		};
+		*visibility = Visibility::Visible;
+		style.left = Val::Px(position.x - node.size().x / 2.);
+		style.top = Val::Px(position.y - node.size().y / 2.);
+		text.sections[0].style.color = match hud_data.reticle {
+			ReticleState::Homing => Color::GREEN,
+			ReticleState::SightLost => Color::GRAY,
+			_ => Color::GOLD,
+		};
 	}
 }
@@ -1,4 +1,6 @@
-use bevy::prelude::*;
+use bevy::{core_pipeline::clear_color::ClearColorConfig, prelude::*};
+
+use crate::camera_rig::CameraRig;
 
 pub mod hud;
 
@@ -8,11 +10,15 @@ impl Plugin for UIPlugin {
 	fn build(&self, app: &mut App) {
 		app
 			.add_systems(Startup, ui_setup)
+			.add_systems(Update, update_ui_camera_clear)
 			.add_plugins(hud::HUDPlugin)
 		;
 	}
 }
 
+/// Rendering order of the UI camera, after any number of player views
+const UI_CAMERA_ORDER: isize = 1000;
+
 #[derive(Component, Clone, Copy, PartialEq, Eq)]
 pub struct UICamera;
 
@@ -23,10 +29,24 @@ fn ui_setup(
 	commands.spawn((
 		Camera2dBundle {
 			camera: Camera {
-				order: -69, //fsr this is needed to be after the main camera (=> negative)
+				// Draws the UI of every player over all of their views, which don't draw any themselves
+				order: UI_CAMERA_ORDER,
 				..Default::default()
 			},
 			..Default::default()
 		}, UICamera
 	));
 }
+
+/// Lets the UI camera clear the window only while there are no player views, those clear it themselves
+fn update_ui_camera_clear(
+	mut ui_cameras: Query<&mut Camera2d, With<UICamera>>,
+	rigs: Query<(), With<CameraRig>>,
+) {
+	let clears = rigs.is_empty();
+	for mut camera_2d in ui_cameras.iter_mut() {
+		if clears == matches!(camera_2d.clear_color, ClearColorConfig::None) {
+			camera_2d.clear_color = if clears {ClearColorConfig::Default} else {ClearColorConfig::None};
+		}
+	}
+}